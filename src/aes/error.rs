@@ -1,10 +1,23 @@
 /// Encrypt Error
-#[derive(Debug, derive_more::From)]
+#[derive(Debug, derive_more::Display, derive_more::From)]
 pub enum EncryptError {
     /// The origin data is too long.
     DataTooLong,
     /// OpenSSL Error
     OpenSSLError(openssl::error::ErrorStack),
     /// Aes key error
+    #[display(fmt = "AesKeyError")]
     AesKeyError(openssl::aes::KeyError),
 }
+
+/// Message decrypt error
+#[derive(Debug, derive_more::Display, derive_more::From)]
+pub enum MessageDecryptError {
+    /// Aes key error
+    #[display(fmt = "AesKeyError")]
+    AesKeyError(openssl::aes::KeyError),
+    /// The length of the encrypted data is not valid.
+    BadLength,
+    /// `msg_key` is not equal to the one calculated from the decrypted data.
+    MsgKeyMismatched,
+}
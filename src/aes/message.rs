@@ -0,0 +1,181 @@
+use super::{EncryptError, MessageDecryptError};
+use bytes::BytesMut;
+use openssl::{
+    aes::{aes_ige, AesKey},
+    sha::Sha256,
+    symm::Mode,
+};
+use rand::{Rng, SeedableRng};
+
+/// The sender of a message. It decides which part of the authorization key is used.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Side {
+    /// Messages from client to server. (`x = 0`)
+    Client,
+    /// Messages from server to client. (`x = 8`)
+    Server,
+}
+
+impl Side {
+    /// Returns `x` used in [key derivation](https://core.telegram.org/mtproto/description#defining-aes-key-and-initialization-vector).
+    fn x(&self) -> usize {
+        match self {
+            Self::Client => 0,
+            Self::Server => 8,
+        }
+    }
+}
+
+/// Calculate `msg_key`: the middle 128 bits of `SHA256(substr(auth_key, 88+x, 32) + plaintext + random_padding)`.
+/// [More](https://core.telegram.org/mtproto/description#message-key-msg-key)
+/// * `plaintext` - Plaintext with padding
+pub fn msg_key(auth_key: &[u8; 256], plaintext: &[u8], side: Side) -> [u8; 16] {
+    msg_key_large(auth_key, plaintext, side)[8..24]
+        .try_into()
+        .unwrap()
+}
+
+/// Calculate the quick ACK token: the first 32 bits of `msg_key_large` with the most significant bit set.
+/// [More](https://core.telegram.org/mtproto/mtproto-transports#quick-ack)
+/// * `plaintext` - Plaintext with padding
+pub fn quick_ack_token(auth_key: &[u8; 256], plaintext: &[u8], side: Side) -> u32 {
+    let msg_key_large = msg_key_large(auth_key, plaintext, side);
+    u32::from_le_bytes(msg_key_large[0..4].try_into().unwrap()) | 0x8000_0000
+}
+
+/// Calculate `msg_key_large`: `SHA256(substr(auth_key, 88+x, 32) + plaintext + random_padding)`.
+fn msg_key_large(auth_key: &[u8; 256], plaintext: &[u8], side: Side) -> [u8; 32] {
+    let x = side.x();
+    let mut hasher = Sha256::new();
+    hasher.update(&auth_key[88 + x..120 + x]);
+    hasher.update(plaintext);
+    hasher.finish()
+}
+
+/// Returns `(aes_key, aes_iv)` derived from the authorization key and `msg_key`.
+/// [More](https://core.telegram.org/mtproto/description#defining-aes-key-and-initialization-vector)
+pub fn message_aes_key_iv(
+    auth_key: &[u8; 256],
+    msg_key: &[u8; 16],
+    side: Side,
+) -> ([u8; 32], [u8; 32]) {
+    let x = side.x();
+    let mut hasher = Sha256::new();
+    hasher.update(msg_key);
+    hasher.update(&auth_key[x..x + 36]);
+    let sha256_a = hasher.finish();
+    let mut hasher = Sha256::new();
+    hasher.update(&auth_key[40 + x..76 + x]);
+    hasher.update(msg_key);
+    let sha256_b = hasher.finish();
+    let mut aes_key = [0u8; 32];
+    aes_key[0..8].copy_from_slice(&sha256_a[0..8]);
+    aes_key[8..24].copy_from_slice(&sha256_b[8..24]);
+    aes_key[24..32].copy_from_slice(&sha256_a[24..32]);
+    let mut aes_iv = [0u8; 32];
+    aes_iv[0..8].copy_from_slice(&sha256_b[0..8]);
+    aes_iv[8..24].copy_from_slice(&sha256_a[8..24]);
+    aes_iv[24..32].copy_from_slice(&sha256_b[24..32]);
+    (aes_key, aes_iv)
+}
+
+/// Encrypt a message with MTProto 2.0. 12 to 1024 bytes of random padding are appended.
+/// Returns `(msg_key, encrypted_data)`.
+/// [More](https://core.telegram.org/mtproto/description#protocol-description)
+/// * `plaintext` - `salt + session_id + message_id + seq_no + message_data_length + message_data`
+pub fn encrypt_message(
+    auth_key: &[u8; 256],
+    plaintext: &[u8],
+    side: Side,
+) -> Result<([u8; 16], BytesMut), EncryptError> {
+    let (msg_key, encrypted_data, _) = encrypt_message_with_quick_ack(auth_key, plaintext, side)?;
+    Ok((msg_key, encrypted_data))
+}
+
+/// Works like [encrypt_message] and also returns the quick ACK token of the message.
+/// Returns `(msg_key, encrypted_data, quick_ack_token)`.
+pub fn encrypt_message_with_quick_ack(
+    auth_key: &[u8; 256],
+    plaintext: &[u8],
+    side: Side,
+) -> Result<([u8; 16], BytesMut, u32), EncryptError> {
+    let le = plaintext.len();
+    let mut padding = 12 + (16 - (le + 12) % 16) % 16;
+    let mut random = rand::rngs::StdRng::from_entropy();
+    padding += 16 * random.gen_range(0..=(1024 - padding) / 16);
+    let mut data = BytesMut::with_capacity(le + padding);
+    data.extend_from_slice(plaintext);
+    data.resize(le + padding, 0);
+    openssl::rand::rand_bytes(&mut data[le..])?;
+    let msg_key = msg_key(auth_key, &data, side);
+    let token = quick_ack_token(auth_key, &data, side);
+    let (aes_key, mut aes_iv) = message_aes_key_iv(auth_key, &msg_key, side);
+    let aes_key = AesKey::new_encrypt(&aes_key)?;
+    let mut encrypted_data = BytesMut::with_capacity(data.len());
+    encrypted_data.resize(data.len(), 0);
+    aes_ige(
+        &data,
+        &mut encrypted_data,
+        &aes_key,
+        &mut aes_iv,
+        Mode::Encrypt,
+    );
+    Ok((msg_key, encrypted_data, token))
+}
+
+/// Decrypt a message with MTProto 2.0 and check `msg_key`. Returns plaintext with padding.
+/// [More](https://core.telegram.org/mtproto/description#protocol-description)
+pub fn decrypt_message(
+    auth_key: &[u8; 256],
+    msg_key: &[u8; 16],
+    encrypted_data: &[u8],
+    side: Side,
+) -> Result<BytesMut, MessageDecryptError> {
+    if encrypted_data.len() < 48 || !encrypted_data.len().is_multiple_of(16) {
+        return Err(MessageDecryptError::BadLength);
+    }
+    let (aes_key, mut aes_iv) = message_aes_key_iv(auth_key, msg_key, side);
+    let aes_key = AesKey::new_decrypt(&aes_key)?;
+    let mut data = BytesMut::with_capacity(encrypted_data.len());
+    data.resize(encrypted_data.len(), 0);
+    aes_ige(
+        encrypted_data,
+        &mut data,
+        &aes_key,
+        &mut aes_iv,
+        Mode::Decrypt,
+    );
+    if !openssl::memcmp::eq(&self::msg_key(auth_key, &data, side), msg_key) {
+        return Err(MessageDecryptError::MsgKeyMismatched);
+    }
+    Ok(data)
+}
+
+#[test]
+fn test_encrypt_message() {
+    let mut auth_key = [0u8; 256];
+    for (i, v) in auth_key.iter_mut().enumerate() {
+        *v = openssl::sha::sha256(&[i as u8])[0];
+    }
+    let plaintext = b"0123456789abcdef0123456789abcdef0123".to_vec();
+    let (msg_key, encrypted) = encrypt_message(&auth_key, &plaintext, Side::Client).unwrap();
+    assert_eq!(encrypted.len() % 16, 0);
+    assert!(encrypted.len() >= plaintext.len() + 12);
+    assert!(encrypted.len() <= plaintext.len() + 1024);
+    let decrypted = decrypt_message(&auth_key, &msg_key, &encrypted, Side::Client).unwrap();
+    assert_eq!(&decrypted[..plaintext.len()], &plaintext[..]);
+    assert!(matches!(
+        decrypt_message(&auth_key, &msg_key, &encrypted, Side::Server),
+        Err(MessageDecryptError::MsgKeyMismatched)
+    ));
+    let mut tampered = encrypted.clone();
+    tampered[0] ^= 1;
+    assert!(matches!(
+        decrypt_message(&auth_key, &msg_key, &tampered, Side::Client),
+        Err(MessageDecryptError::MsgKeyMismatched)
+    ));
+    assert!(matches!(
+        decrypt_message(&auth_key, &msg_key, &encrypted[..32], Side::Client),
+        Err(MessageDecryptError::BadLength)
+    ));
+}
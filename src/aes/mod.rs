@@ -1,15 +1,52 @@
 mod error;
+/// MTProto 2.0 message encryption
+mod message;
 
+use crate::objects::{base::I256, traits::Serialize};
 use bytes::BytesMut;
 pub use error::EncryptError;
+pub use error::MessageDecryptError;
+pub use message::{
+    decrypt_message, encrypt_message, encrypt_message_with_quick_ack, message_aes_key_iv, msg_key,
+    quick_ack_token, Side,
+};
 use openssl::{
     aes::{aes_ige, AesKey},
     bn::BigNum,
     pkey::Public,
     rsa::{Padding, Rsa},
-    sha::{sha256, Sha256},
+    sha::{sha256, Sha1, Sha256},
 };
 
+/// Generate `tmp_aes_key` and `tmp_aes_iv` which are used to encrypt `server_DH_inner_data` and `client_DH_inner_data`.
+/// [More](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication)
+///
+/// Returns `(tmp_aes_key, tmp_aes_iv)`
+/// * `new_nonce` - Client-generated random number in [Step 4](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication)
+/// * `server_nonce` - Value received from server in [Step 2](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation)
+pub fn tmp_aes_key_iv(new_nonce: &I256, server_nonce: i128) -> (BytesMut, BytesMut) {
+    let new_nonce = new_nonce.serialize_to_bytes();
+    let server_nonce = server_nonce.serialize_to_bytes();
+    let mut hasher = Sha1::new();
+    hasher.update(&new_nonce);
+    hasher.update(&server_nonce);
+    let mut tmp_aes_key = BytesMut::with_capacity(32);
+    tmp_aes_key.extend_from_slice(&hasher.finish());
+    let mut hasher = Sha1::new();
+    hasher.update(&server_nonce);
+    hasher.update(&new_nonce);
+    let hashre = hasher.finish();
+    tmp_aes_key.extend_from_slice(&hashre[0..12]);
+    let mut tmp_aes_iv = BytesMut::with_capacity(32);
+    tmp_aes_iv.extend_from_slice(&hashre[12..20]);
+    let mut hasher = Sha1::new();
+    hasher.update(&new_nonce);
+    hasher.update(&new_nonce);
+    tmp_aes_iv.extend_from_slice(&hasher.finish());
+    tmp_aes_iv.extend_from_slice(&new_nonce[0..4]);
+    (tmp_aes_key, tmp_aes_iv)
+}
+
 /// Used to generate key_aes_encrypted
 fn rsa_pad_internal(
     data_with_padding: &BytesMut,
@@ -23,7 +60,7 @@ fn rsa_pad_internal(
     let mut data_with_hash = data_pad_reversed.clone();
     let mut hasher = Sha256::new();
     hasher.update(&temp_key);
-    hasher.update(&data_with_padding);
+    hasher.update(data_with_padding);
     data_with_hash.extend_from_slice(&hasher.finish());
     let mut iv = BytesMut::with_capacity(32);
     iv.resize(32, 0);
@@ -0,0 +1,109 @@
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// The maximum number of message ids in one `msgs_ack`.
+const MAX_ACK_IDS: usize = 8192;
+
+/// Collects the ids of received content-related messages which are not acknowledged yet.
+/// [More](https://core.telegram.org/mtproto/service_messages_about_messages#acknowledgment-of-receipt)
+#[derive(Debug)]
+pub struct AckManager {
+    /// Message ids waiting for acknowledgment
+    ids: Mutex<Vec<i64>>,
+    /// The number of ids which triggers flushing immediately.
+    batch_size: usize,
+    /// Notified when the first id is added.
+    arrived: Notify,
+    /// Notified when the batch is full.
+    full: Notify,
+}
+
+impl AckManager {
+    /// Create a new manager.
+    /// * `batch_size` - The number of ids which triggers flushing immediately.
+    pub fn new(batch_size: usize) -> Self {
+        Self {
+            ids: Mutex::new(Vec::new()),
+            batch_size: batch_size.clamp(1, MAX_ACK_IDS),
+            arrived: Notify::new(),
+            full: Notify::new(),
+        }
+    }
+
+    /// Add ids of messages to acknowledge.
+    pub fn push(&self, msg_ids: &[i64]) {
+        if msg_ids.is_empty() {
+            return;
+        }
+        // Only current waiters are woken up. A stored permit would outlive the batch
+        // and make the next wait return at once, so waiters check the ids again.
+        let mut ids = self.ids.lock().unwrap();
+        if ids.is_empty() {
+            self.arrived.notify_waiters();
+        }
+        ids.extend_from_slice(msg_ids);
+        if ids.len() >= self.batch_size {
+            self.full.notify_waiters();
+        }
+    }
+
+    /// Take the ids to acknowledge, at most [MAX_ACK_IDS] at once.
+    pub fn take(&self) -> Vec<i64> {
+        let mut ids = self.ids.lock().unwrap();
+        if ids.len() > MAX_ACK_IDS {
+            let rest = ids.split_off(MAX_ACK_IDS);
+            std::mem::replace(&mut *ids, rest)
+        } else {
+            std::mem::take(&mut *ids)
+        }
+    }
+
+    /// Wait until there is an id to acknowledge.
+    pub async fn arrived(&self) {
+        loop {
+            let notified = self.arrived.notified();
+            if !self.ids.lock().unwrap().is_empty() {
+                return;
+            }
+            notified.await;
+        }
+    }
+
+    /// Wait until the batch is full.
+    pub async fn full(&self) {
+        loop {
+            let notified = self.full.notified();
+            if self.ids.lock().unwrap().len() >= self.batch_size {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_ack_manager() {
+    let manager = AckManager::new(3);
+    manager.push(&[4, 8]);
+    manager.arrived().await;
+    assert!(
+        tokio::time::timeout(std::time::Duration::from_millis(10), manager.full())
+            .await
+            .is_err()
+    );
+    manager.push(&[12]);
+    manager.full().await;
+    assert_eq!(manager.take(), vec![4, 8, 12]);
+    assert!(manager.take().is_empty());
+
+    // A full batch taken without waiting does not cut the next delay short.
+    manager.push(&[16, 20, 24]);
+    manager.take();
+    manager.push(&[28]);
+    assert!(
+        tokio::time::timeout(std::time::Duration::from_millis(10), manager.full())
+            .await
+            .is_err()
+    );
+}
@@ -0,0 +1,64 @@
+use openssl::sha::sha1;
+
+/// Authorization key. [More](https://core.telegram.org/mtproto/auth_key)
+#[derive(Clone)]
+pub struct AuthKey {
+    /// 2048-bit key
+    data: [u8; 256],
+    /// 64 lower-order bits of SHA1 of the key
+    id: i64,
+    /// 64 higher-order bits of SHA1 of the key
+    aux_hash: i64,
+}
+
+impl AuthKey {
+    /// Create a new instance from raw key.
+    pub fn new(data: [u8; 256]) -> Self {
+        let hash = sha1(&data);
+        let aux_hash = i64::from_le_bytes(hash[0..8].try_into().unwrap());
+        let id = i64::from_le_bytes(hash[12..20].try_into().unwrap());
+        Self { data, id, aux_hash }
+    }
+
+    /// Create a new instance from a big endian number. Returns [None] if the number is longer than 256 bytes.
+    pub fn from_slice(data: &[u8]) -> Option<Self> {
+        if data.len() > 256 {
+            return None;
+        }
+        let mut key = [0u8; 256];
+        key[256 - data.len()..].copy_from_slice(data);
+        Some(Self::new(key))
+    }
+
+    /// Returns the raw key.
+    pub fn as_bytes(&self) -> &[u8; 256] {
+        &self.data
+    }
+
+    /// Returns `auth_key_id`, the 64 lower-order bits of SHA1 of the key.
+    pub fn id(&self) -> i64 {
+        self.id
+    }
+
+    /// Returns `auth_key_aux_hash`, the 64 higher-order bits of SHA1 of the key.
+    pub fn aux_hash(&self) -> i64 {
+        self.aux_hash
+    }
+}
+
+impl std::fmt::Debug for AuthKey {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("AuthKey").field("id", &self.id).finish()
+    }
+}
+
+/// The result of [super::Client::generate_auth_key]
+#[derive(Clone, Debug)]
+pub struct GeneratedAuthKey {
+    /// Authorization key
+    pub auth_key: AuthKey,
+    /// The initial server salt. `substr(new_nonce, 0, 8) XOR substr(server_nonce, 0, 8)`
+    pub server_salt: i64,
+    /// The difference between server time and local time in seconds.
+    pub time_offset: i32,
+}
@@ -1,14 +1,37 @@
-use super::ClientError;
-use crate::objects::base::UnencryptedMessage;
-use crate::objects::traits::{Deserialize, Serialize};
+use super::obfuscation::{init_payload, protocol_tag, AesCtr};
+use super::proxy::{
+    client_hello, verify_server_hello, MtProxy, MtProxySecret, CHANGE_CIPHER_SPEC,
+    MAX_TLS_RECORD_LEN,
+};
+use super::quick_ack::{QuickAck, QuickAcks};
+use super::tunnel::Proxy;
+use super::websocket::{
+    encode_frame, handshake_request, verify_handshake_response, WebSocket, OPCODE_BINARY,
+    OPCODE_CLOSE, OPCODE_CONTINUATION, OPCODE_PING, OPCODE_PONG,
+};
+use super::{AuthKey, ClientError, ConnectionState, MessageIdGenerator, Session};
+use crate::aes::{decrypt_message, encrypt_message_with_quick_ack, Side};
+use crate::objects::base::{DecryptedMessage, EncryptedMessage, UnencryptedMessage};
+use crate::objects::mtproto_api::constructors::message;
+use crate::objects::mtproto_api::types::RSAPublicKey;
+use crate::objects::traits::{Deserialize, Function, Serialize};
 use bytes::BytesMut;
-use futures_util::lock::Mutex;
+use futures_util::lock::{Mutex, MutexGuard};
+use futures_util::stream::{FuturesUnordered, StreamExt};
+use openssl::ssl::{SslConnector, SslMethod};
 use rand::{Rng, SeedableRng};
-use std::ops::DerefMut;
-use std::sync::atomic::{AtomicBool, AtomicU32, Ordering};
-use std::time::{SystemTime, UNIX_EPOCH};
-use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use socket2::{SockRef, TcpKeepalive};
+use std::net::SocketAddr;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU32, AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::tcp::{OwnedReadHalf, OwnedWriteHalf};
 use tokio::net::{TcpStream, ToSocketAddrs, UdpSocket};
+use tokio::sync::watch;
+use tokio::time::Instant;
+use tokio_openssl::SslStream;
 
 /// [Client] builder
 pub struct ClientBuilder {
@@ -17,26 +40,251 @@ pub struct ClientBuilder {
     /// Use UDP Connections
     _use_udp: bool,
     _transport_type: TransportType,
+    /// Enable transport obfuscation
+    _obfuscated: bool,
+    /// The maximum time to wait before acknowledging received messages
+    _ack_delay: Duration,
+    /// The number of message ids which are acknowledged at once
+    _ack_batch_size: usize,
+    /// The interval between pings
+    _ping_interval: Duration,
+    /// The delay after which server closes the connection if no ping is received
+    _disconnect_delay: Duration,
+    /// MTProxy
+    _mtproxy: Option<MtProxy>,
+    /// SOCKS5 or HTTP proxy
+    _proxy: Option<Proxy>,
+    /// The host and port which the proxy connects to
+    _proxy_target: Option<String>,
+    /// The DC id sent to MTProxy
+    _dc_id: i16,
+    /// WebSocket endpoint
+    _websocket: Option<WebSocket>,
+    /// The maximum length of a received frame
+    _max_frame_len: usize,
+    /// Publishes the connection state
+    _state: Arc<watch::Sender<ConnectionState>>,
+    /// The delay before the first reconnection attempt
+    _reconnect_delay: Duration,
+    /// The maximum delay between reconnection attempts
+    _max_reconnect_delay: Duration,
+    /// The maximum number of failed reconnection attempts before giving up
+    _max_reconnect_attempts: Option<u32>,
+    /// The maximum time to connect and initialize the transport
+    _connect_timeout: Option<Duration>,
+    /// The maximum time to receive the rest of a frame
+    _read_timeout: Option<Duration>,
+    /// The maximum time to wait for a frame
+    _idle_timeout: Option<Duration>,
+    /// The idle time before TCP keepalive probes are sent
+    _tcp_keepalive: Option<Duration>,
+    /// Server public keys used to generate a new authorization key on reconnection
+    _public_keys: Vec<RSAPublicKey>,
+    /// The delay between connection attempts to different endpoints
+    _happy_eyeballs_delay: Duration,
 }
 
 impl ClientBuilder {
     /// Build the client
     /// * `address` - The address to connect to.
-    pub async fn build<A: ToSocketAddrs>(self, address: A) -> Result<Client, ClientError> {
-        let stream = if self._use_udp {
+    ///   It is ignored if [ClientBuilder::mtproxy], [ClientBuilder::websocket]
+    ///   or [ClientBuilder::proxy_target] is set.
+    pub async fn build<A: ToSocketAddrs>(mut self, address: A) -> Result<Client, ClientError> {
+        self.check()?;
+        self._state.send_replace(ConnectionState::Connecting);
+        let result = match self._connect_timeout {
+            Some(connect_timeout) => tokio::time::timeout(connect_timeout, self.connect(address))
+                .await
+                .unwrap_or(Err(ClientError::ConnectTimeout)),
+            None => self.connect(address).await,
+        };
+        match result {
+            Ok((stream, host)) => Ok(self.into_client(stream, host)),
+            Err(e) => {
+                self._state.send_replace(ConnectionState::Closed);
+                Err(e)
+            }
+        }
+    }
+
+    /// Build the client with the first of the endpoints whose transport is initialized.
+    /// Connections are raced [happy-eyeballs](https://www.rfc-editor.org/rfc/rfc8305) style:
+    /// IPv6 endpoints are tried first, alternating with IPv4 ones, and each attempt starts
+    /// [ClientBuilder::happy_eyeballs_delay] after the previous one or as soon as it fails.
+    /// The error of the last attempt is returned if every attempt fails.
+    /// With [ClientBuilder::mtproxy], [ClientBuilder::proxy] or [ClientBuilder::websocket],
+    /// only the first endpoint is tried since every attempt would go through the same proxy.
+    /// * `endpoints` - The endpoints of the DC
+    pub async fn build_any(mut self, endpoints: &[SocketAddr]) -> Result<Client, ClientError> {
+        self.check()?;
+        if endpoints.is_empty() {
+            return Err(ClientError::TokioError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "No endpoint is given.",
+            )));
+        }
+        let endpoints =
+            if self._mtproxy.is_some() || self._proxy.is_some() || self._websocket.is_some() {
+                &endpoints[..1]
+            } else {
+                endpoints
+            };
+        self._state.send_replace(ConnectionState::Connecting);
+        let mut remaining = interleave(endpoints).into_iter().peekable();
+        let mut next = remaining.next();
+        let mut attempts = FuturesUnordered::new();
+        let mut error = None;
+        loop {
+            if let Some(endpoint) = next.take() {
+                let builder = self.clone();
+                attempts.push(async move {
+                    let client = builder.build(endpoint).await?;
+                    client.init().await?;
+                    Ok::<_, ClientError>(client)
+                });
+            }
+            let result = if remaining.peek().is_some() {
+                match tokio::time::timeout(self._happy_eyeballs_delay, attempts.next()).await {
+                    Ok(result) => result,
+                    Err(_) => {
+                        next = remaining.next();
+                        continue;
+                    }
+                }
+            } else {
+                attempts.next().await
+            };
+            match result {
+                Some(Ok(mut client)) => {
+                    // The other attempts are cancelled when they are dropped.
+                    client.builder._state = self._state.clone();
+                    self._state.send_replace(ConnectionState::Ready);
+                    return Ok(client);
+                }
+                Some(Err(e)) => {
+                    error = Some(e);
+                    next = remaining.next();
+                }
+                None => break,
+            }
+        }
+        self._state.send_replace(ConnectionState::Closed);
+        Err(error.unwrap_or(ClientError::Disconnected))
+    }
+
+    /// Connect to the server. Returns the socket and the value of `Host` header.
+    async fn connect<A: ToSocketAddrs>(
+        &self,
+        address: A,
+    ) -> Result<(SocketHelper, String), ClientError> {
+        let (stream, host) = if self._use_udp {
+            if self._mtproxy.is_some() || self._proxy.is_some() || self._transport_type.is_http() {
+                return Err(ClientError::UnsupportedTransport);
+            }
             let socket = UdpSocket::bind("0.0.0.0:0").await?;
             socket.connect(address).await?;
-            SocketHelper::from(socket)
+            let host = socket.peer_addr()?.to_string();
+            (SocketHelper::from(socket), host)
         } else {
-            let stream = TcpStream::connect(address).await?;
+            let endpoint = self
+                ._mtproxy
+                .as_ref()
+                .map(|v| v.address().to_string())
+                .or_else(|| self._websocket.as_ref().map(|v| v.address()));
+            let (stream, host) = match (&self._proxy, endpoint) {
+                (Some(proxy), Some(endpoint)) => (proxy.connect(&endpoint).await?, endpoint),
+                (Some(proxy), None) => {
+                    let target = match &self._proxy_target {
+                        Some(target) => target.clone(),
+                        None => resolve(address).await?.to_string(),
+                    };
+                    (proxy.connect(&target).await?, target)
+                }
+                (None, Some(endpoint)) => (TcpStream::connect(&endpoint).await?, endpoint),
+                (None, None) => {
+                    let stream = TcpStream::connect(address).await?;
+                    let host = stream.peer_addr()?.to_string();
+                    (stream, host)
+                }
+            };
             stream.set_nodelay(self._no_delay)?;
-            SocketHelper::from(stream)
+            if let Some(time) = self._tcp_keepalive {
+                SockRef::from(&stream).set_tcp_keepalive(&TcpKeepalive::new().with_time(time))?;
+            }
+            match &self._websocket {
+                Some(websocket) if websocket.is_secure() => (
+                    SocketHelper::from_stream(connect_tls(stream, websocket.host()).await?),
+                    host,
+                ),
+                _ => (SocketHelper::from(stream), host),
+            }
         };
-        Ok(Client {
+        Ok((stream, host))
+    }
+
+    /// Build the client over a connected stream, e.g. a Unix socket or [tokio::io::duplex].
+    /// Obfuscation, MTProxy and WebSocket are applied over the stream if they are set.
+    /// [ClientBuilder::proxy] and [ClientBuilder::use_udp] can not be used.
+    /// * `stream` - The stream to the server
+    pub fn build_with_stream<S>(mut self, stream: S) -> Result<Client, ClientError>
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        self.check()?;
+        if self._use_udp || self._proxy.is_some() {
+            return Err(ClientError::UnsupportedTransport);
+        }
+        let host = self
+            ._websocket
+            .as_ref()
+            .map(|v| v.host().to_string())
+            .unwrap_or_default();
+        self._state.send_replace(ConnectionState::Connecting);
+        Ok(self.into_client(SocketHelper::from_stream(stream), host))
+    }
+
+    /// Returns a receiver of the state of the connection which is being built.
+    pub fn state(&self) -> watch::Receiver<ConnectionState> {
+        self._state.subscribe()
+    }
+
+    /// Apply the options which imply others and check whether they can be used together.
+    fn check(&mut self) -> Result<(), ClientError> {
+        if let Some(ty) = self
+            ._mtproxy
+            .as_ref()
+            .and_then(|v| v.secret().transport_type())
+        {
+            self._transport_type = ty;
+        }
+        if self._websocket.is_some() {
+            if self._mtproxy.is_some() || self._use_udp {
+                return Err(ClientError::UnsupportedTransport);
+            }
+            // WebSocket connections are always obfuscated.
+            self._obfuscated = true;
+        }
+        if (self._obfuscated || self._mtproxy.is_some())
+            && (self._transport_type.is_full() || self._transport_type.is_http())
+        {
+            return Err(ClientError::UnsupportedTransport);
+        }
+        Ok(())
+    }
+
+    /// Create the client with the connected socket.
+    fn into_client(self, stream: SocketHelper, host: String) -> Client {
+        Client {
             stream,
             builder: self,
-            seq_no: AtomicU32::new(0),
-        })
+            seq_no: Mutex::new(0),
+            send_lock: Mutex::new(()),
+            msg_id: MessageIdGenerator::new(),
+            host,
+            http_requests: AtomicUsize::new(0),
+            quick_acks: QuickAcks::default(),
+            recv_seq_no: AtomicU32::new(0),
+        }
     }
 
     /// Create a new builder
@@ -45,15 +293,174 @@ impl ClientBuilder {
             _no_delay: false,
             _use_udp: false,
             _transport_type: TransportType::Full,
+            _obfuscated: false,
+            _ack_delay: Duration::from_millis(500),
+            _ack_batch_size: 16,
+            _ping_interval: Duration::from_secs(60),
+            _disconnect_delay: Duration::from_secs(75),
+            _mtproxy: None,
+            _proxy: None,
+            _proxy_target: None,
+            _dc_id: 0,
+            _websocket: None,
+            _max_frame_len: 16 * 1024 * 1024,
+            _state: Arc::new(watch::channel(ConnectionState::Connecting).0),
+            _reconnect_delay: Duration::from_secs(1),
+            _max_reconnect_delay: Duration::from_secs(60),
+            _max_reconnect_attempts: None,
+            _connect_timeout: None,
+            _read_timeout: None,
+            _idle_timeout: None,
+            _tcp_keepalive: None,
+            _public_keys: Vec::new(),
+            _happy_eyeballs_delay: Duration::from_millis(250),
         }
     }
 
+    /// Set the number of received messages which are acknowledged immediately
+    /// without waiting for [ClientBuilder::ack_delay]. Default: 16
+    pub fn ack_batch_size(mut self, ack_batch_size: usize) -> Self {
+        self._ack_batch_size = ack_batch_size;
+        self
+    }
+
+    /// Set the maximum time to wait before acknowledging received messages.
+    /// Pending acknowledgments are also sent with the next request. Default: 500ms
+    pub fn ack_delay(mut self, ack_delay: Duration) -> Self {
+        self._ack_delay = ack_delay;
+        self
+    }
+
+    /// Set the delay after which server closes the connection if no more ping is received.
+    /// It should be longer than [ClientBuilder::ping_interval]. Default: 75s
+    pub fn disconnect_delay(mut self, disconnect_delay: Duration) -> Self {
+        self._disconnect_delay = disconnect_delay;
+        self
+    }
+
+    /// Set the maximum time to connect, including proxies and TLS, and to initialize the transport.
+    /// [ClientError::ConnectTimeout] is returned if it elapses. Default: unlimited
+    pub fn connect_timeout(mut self, connect_timeout: Duration) -> Self {
+        self._connect_timeout = Some(connect_timeout);
+        self
+    }
+
+    /// Set the DC id. It is required by [ClientBuilder::mtproxy].
+    /// Test DCs are `10000 + id` and media DCs are negative.
+    pub fn dc_id(mut self, dc_id: i16) -> Self {
+        self._dc_id = dc_id;
+        self
+    }
+
+    /// Set the delay before the next endpoint is tried by [ClientBuilder::build_any]
+    /// if the previous attempt neither succeeds nor fails. Default: 250ms
+    pub fn happy_eyeballs_delay(mut self, happy_eyeballs_delay: Duration) -> Self {
+        self._happy_eyeballs_delay = happy_eyeballs_delay;
+        self
+    }
+
+    /// Set the maximum time to wait for a frame. [ClientError::IdleTimeout] is returned if it elapses.
+    /// It should be longer than [ClientBuilder::ping_interval] so that pongs keep the connection alive.
+    /// Default: unlimited
+    pub fn idle_timeout(mut self, idle_timeout: Duration) -> Self {
+        self._idle_timeout = Some(idle_timeout);
+        self
+    }
+
+    /// Set the maximum length of a received frame. Longer frames are rejected
+    /// before a buffer is allocated. Default: 16MB
+    pub fn max_frame_len(mut self, max_frame_len: usize) -> Self {
+        self._max_frame_len = max_frame_len;
+        self
+    }
+
+    /// Set the number of failed reconnection attempts after which
+    /// [ReconnectingClient](super::ReconnectingClient) gives up. Default: unlimited
+    pub fn max_reconnect_attempts(mut self, max_reconnect_attempts: u32) -> Self {
+        self._max_reconnect_attempts = Some(max_reconnect_attempts);
+        self
+    }
+
+    /// Set the maximum delay between reconnection attempts of [ReconnectingClient](super::ReconnectingClient).
+    /// It is also used after [TransportError::Flood](super::TransportError::Flood). Default: 60s
+    pub fn max_reconnect_delay(mut self, max_reconnect_delay: Duration) -> Self {
+        self._max_reconnect_delay = max_reconnect_delay;
+        self
+    }
+
+    /// Connect through MTProxy. The connection is always obfuscated.
+    /// [TransportType::PaddedIntermediate] is used if the secret requires it.
+    pub fn mtproxy(mut self, proxy: MtProxy) -> Self {
+        self._mtproxy = Some(proxy);
+        self
+    }
+
     /// Set `TCP_NODELAY`
     pub fn no_delay(mut self, no_delay: bool) -> Self {
         self._no_delay = no_delay;
         self
     }
 
+    /// Enable [transport obfuscation](https://core.telegram.org/mtproto/mtproto-transports#transport-obfuscation).
+    /// [TransportType::Full] can not be obfuscated.
+    pub fn obfuscated(mut self, obfuscated: bool) -> Self {
+        self._obfuscated = obfuscated;
+        self
+    }
+
+    /// Connect through a SOCKS5 or HTTP proxy. The proxy resolves the host of
+    /// [ClientBuilder::proxy_target], [ClientBuilder::mtproxy] or [ClientBuilder::websocket],
+    /// and the address given to [ClientBuilder::build] is resolved locally otherwise.
+    /// It can be used together with [ClientBuilder::mtproxy].
+    pub fn proxy(mut self, proxy: Proxy) -> Self {
+        self._proxy = Some(proxy);
+        self
+    }
+
+    /// Set the host and port which [ClientBuilder::proxy] connects to, e.g. `example.com:443`.
+    /// The host is resolved by the proxy. It is ignored if [ClientBuilder::mtproxy]
+    /// or [ClientBuilder::websocket] is set.
+    pub fn proxy_target(mut self, target: &str) -> Self {
+        self._proxy_target = Some(target.to_string());
+        self
+    }
+
+    /// Set the interval between pings. The connection is considered dead if `pong` is not
+    /// received within [ClientBuilder::disconnect_delay] minus the interval. Default: 60s
+    pub fn ping_interval(mut self, ping_interval: Duration) -> Self {
+        self._ping_interval = ping_interval;
+        self
+    }
+
+    /// Set server public keys. [ReconnectingClient](super::ReconnectingClient) generates
+    /// a new authorization key with them when server does not know the current one
+    /// or the DC is switched. It gives up then if no key is set.
+    pub fn public_keys(mut self, public_keys: Vec<RSAPublicKey>) -> Self {
+        self._public_keys = public_keys;
+        self
+    }
+
+    /// Set the maximum time to receive the rest of a frame after its first bytes are received.
+    /// [ClientError::ReadTimeout] is returned if it elapses. Default: unlimited
+    pub fn read_timeout(mut self, read_timeout: Duration) -> Self {
+        self._read_timeout = Some(read_timeout);
+        self
+    }
+
+    /// Set the delay before the first reconnection attempt of [ReconnectingClient](super::ReconnectingClient).
+    /// The delay is doubled after each failed attempt, up to [ClientBuilder::max_reconnect_delay]. Default: 1s
+    pub fn reconnect_delay(mut self, reconnect_delay: Duration) -> Self {
+        self._reconnect_delay = reconnect_delay;
+        self
+    }
+
+    /// Enable TCP keepalive. Probes are sent after the connection is idle for the time.
+    /// Default: disabled
+    pub fn tcp_keepalive(mut self, time: Duration) -> Self {
+        self._tcp_keepalive = Some(time);
+        self
+    }
+
     /// Set the transport type. Default: [TransportType::Full]
     pub fn transport_type(mut self, transport_type: TransportType) -> Self {
         self._transport_type = transport_type;
@@ -65,6 +472,93 @@ impl ClientBuilder {
         self._use_udp = use_udp;
         self
     }
+
+    /// Transport the connection in binary WebSocket messages. The connection is always obfuscated
+    /// and the address passed to [ClientBuilder::build] is ignored.
+    /// It can not be used with [ClientBuilder::mtproxy] or UDP.
+    pub fn websocket(mut self, websocket: WebSocket) -> Self {
+        self._websocket = Some(websocket);
+        self
+    }
+}
+
+impl Default for ClientBuilder {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl Clone for ClientBuilder {
+    /// Clone the options. The clone publishes the state of its own connections.
+    fn clone(&self) -> Self {
+        Self {
+            _no_delay: self._no_delay,
+            _use_udp: self._use_udp,
+            _transport_type: self._transport_type.clone(),
+            _obfuscated: self._obfuscated,
+            _ack_delay: self._ack_delay,
+            _ack_batch_size: self._ack_batch_size,
+            _ping_interval: self._ping_interval,
+            _disconnect_delay: self._disconnect_delay,
+            _mtproxy: self._mtproxy.clone(),
+            _proxy: self._proxy.clone(),
+            _proxy_target: self._proxy_target.clone(),
+            _dc_id: self._dc_id,
+            _websocket: self._websocket.clone(),
+            _max_frame_len: self._max_frame_len,
+            _state: Arc::new(watch::channel(ConnectionState::Connecting).0),
+            _reconnect_delay: self._reconnect_delay,
+            _max_reconnect_delay: self._max_reconnect_delay,
+            _max_reconnect_attempts: self._max_reconnect_attempts,
+            _connect_timeout: self._connect_timeout,
+            _read_timeout: self._read_timeout,
+            _idle_timeout: self._idle_timeout,
+            _tcp_keepalive: self._tcp_keepalive,
+            _public_keys: self._public_keys.clone(),
+            _happy_eyeballs_delay: self._happy_eyeballs_delay,
+        }
+    }
+}
+
+/// Resolve the address and returns the first one.
+async fn resolve<A: ToSocketAddrs>(address: A) -> Result<SocketAddr, ClientError> {
+    tokio::net::lookup_host(address)
+        .await?
+        .next()
+        .ok_or_else(|| {
+            ClientError::TokioError(std::io::Error::new(
+                std::io::ErrorKind::NotFound,
+                "No address is resolved.",
+            ))
+        })
+}
+
+/// Order the endpoints to be tried, alternating IPv6 and IPv4 ones and starting with IPv6.
+fn interleave(endpoints: &[SocketAddr]) -> Vec<SocketAddr> {
+    let (v6, v4): (Vec<SocketAddr>, Vec<SocketAddr>) = endpoints.iter().partition(|v| v.is_ipv6());
+    let (mut v6, mut v4) = (v6.into_iter(), v4.into_iter());
+    let mut sorted = Vec::with_capacity(endpoints.len());
+    loop {
+        match (v6.next(), v4.next()) {
+            (None, None) => break,
+            (a, b) => sorted.extend(a.into_iter().chain(b)),
+        }
+    }
+    sorted
+}
+
+/// Wrap the stream in TLS.
+async fn connect_tls(stream: TcpStream, domain: &str) -> Result<SslStream<TcpStream>, ClientError> {
+    let ssl = SslConnector::builder(SslMethod::tls_client())?
+        .build()
+        .configure()?
+        .into_ssl(domain)?;
+    let mut stream = SslStream::new(ssl, stream)?;
+    Pin::new(&mut stream)
+        .connect()
+        .await
+        .map_err(|e| e.into_io_error().unwrap_or_else(std::io::Error::other))?;
+    Ok(stream)
 }
 
 #[derive(Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
@@ -77,6 +571,7 @@ pub enum TransportType {
     /// [More](https://core.telegram.org/mtproto/mtproto-transports#intermediate)
     Intermediate,
     /// Can be to use with [obfuscation enabled](https://core.telegram.org/mtproto/mtproto-transports#transport-obfsucation) to bypass ISP blocks.
+    /// See [ClientBuilder::obfuscated].
     /// Max length of the payload: `4294967280 - 4294967295`.
     /// [More](https://core.telegram.org/mtproto/mtproto-transports#padded-intermediate)
     PaddedIntermediate,
@@ -84,6 +579,11 @@ pub enum TransportType {
     /// Max length of the payload: `4294967287`.
     /// [More](https://core.telegram.org/mtproto/mtproto-transports#full)
     Full,
+    /// Each payload is sent in the body of an HTTP `POST` request to `/api`
+    /// and received in the body of the response.
+    /// Messages initiated by server are received by long polling with `http_wait`.
+    /// [More](https://core.telegram.org/mtproto/transports#http)
+    Http,
 }
 
 impl TransportType {
@@ -91,79 +591,367 @@ impl TransportType {
     pub fn is_full(&self) -> bool {
         *self == Self::Full
     }
+
+    /// Returns true if current variant is [TransportType::Http]
+    pub fn is_http(&self) -> bool {
+        *self == Self::Http
+    }
 }
 
-/// Socket wrapper
-enum Socket {
+/// The receiving half of a socket
+enum ReadHalf {
+    /// TCP
+    Tcp(OwnedReadHalf),
+    /// Any other stream
+    Stream(Box<dyn AsyncRead + Unpin + Send>),
+    /// UDP
+    Udp(Arc<UdpSocket>),
+}
+
+/// The sending half of a socket
+enum WriteHalf {
     /// TCP
-    TCP(TcpStream),
+    Tcp(OwnedWriteHalf),
+    /// Any other stream
+    Stream(Box<dyn AsyncWrite + Unpin + Send>),
     /// UDP
-    UDP(UdpSocket),
+    Udp(Arc<UdpSocket>),
+}
+
+/// The receiving half of a socket and its state
+struct Reader {
+    /// Socket
+    socket: ReadHalf,
+    /// Decrypts incoming data if the connection is obfuscated.
+    decryptor: Option<AesCtr>,
+    /// Whether data is wrapped in TLS records
+    tls: bool,
+    /// The number of bytes left in the current TLS record
+    tls_remaining: usize,
+    /// Whether data is wrapped in WebSocket frames
+    websocket: bool,
+    /// The number of bytes left in the current WebSocket frame
+    ws_remaining: usize,
+}
+
+impl Reader {
+    /// Read exactly `data.len()` bytes from the socket.
+    async fn read_raw(&mut self, data: &mut [u8]) -> Result<(), ClientError> {
+        let le = data.len();
+        let mut s = 0;
+        while s < le {
+            let n = match &mut self.socket {
+                ReadHalf::Tcp(stream) => stream.read(&mut data[s..]).await?,
+                ReadHalf::Stream(stream) => stream.read(&mut data[s..]).await?,
+                ReadHalf::Udp(stream) => stream.recv(&mut data[s..]).await?,
+            };
+            // The peer closed the stream. An empty datagram is still valid.
+            if n == 0 && !matches!(self.socket, ReadHalf::Udp(_)) {
+                return Err(ClientError::Disconnected);
+            }
+            s += n;
+        }
+        Ok(())
+    }
+
+    /// Read a TLS record and returns it including the header.
+    async fn read_tls_record(&mut self) -> Result<Vec<u8>, ClientError> {
+        let mut record = vec![0u8; 5];
+        self.read_raw(&mut record).await?;
+        let le = u16::from_be_bytes([record[3], record[4]]) as usize;
+        record.resize(5 + le, 0);
+        self.read_raw(&mut record[5..]).await?;
+        Ok(record)
+    }
+
+    /// Read the header of a WebSocket frame and returns the length of its payload.
+    /// The payload of control frames is consumed, and a ping is answered at once.
+    /// * `writer` - The sending half
+    async fn read_ws_frame(&mut self, writer: &Mutex<Writer>) -> Result<usize, ClientError> {
+        let mut header = [0u8; 2];
+        self.read_raw(&mut header).await?;
+        // Frames sent by server are never masked.
+        if header[1] & 0x80 != 0 {
+            return Err(ClientError::BadWebSocketFrame);
+        }
+        let le = match header[1] & 0x7f {
+            126 => {
+                let mut le = [0u8; 2];
+                self.read_raw(&mut le).await?;
+                u16::from_be_bytes(le) as usize
+            }
+            127 => {
+                let mut le = [0u8; 8];
+                self.read_raw(&mut le).await?;
+                u64::from_be_bytes(le) as usize
+            }
+            le => le as usize,
+        };
+        match header[0] & 0x0f {
+            OPCODE_CONTINUATION | OPCODE_BINARY => Ok(le),
+            opcode @ (OPCODE_CLOSE | OPCODE_PING | OPCODE_PONG) if le < 126 => {
+                let mut payload = vec![0u8; le];
+                self.read_raw(&mut payload).await?;
+                match opcode {
+                    OPCODE_CLOSE => Err(ClientError::Disconnected),
+                    OPCODE_PING => {
+                        let frame = encode_frame(OPCODE_PONG, &payload)?;
+                        writer.lock().await.socket.write_all(&frame).await?;
+                        Ok(0)
+                    }
+                    _ => Ok(0),
+                }
+            }
+            _ => Err(ClientError::BadWebSocketFrame),
+        }
+    }
+
+    /// Read exactly `data.len()` bytes, unwrapping TLS records or WebSocket frames
+    /// and decrypting if required.
+    /// * `writer` - The sending half, which WebSocket pings are answered over
+    async fn read_exact(
+        &mut self,
+        data: &mut [u8],
+        writer: &Mutex<Writer>,
+    ) -> Result<(), ClientError> {
+        if self.tls {
+            let mut s = 0;
+            while s < data.len() {
+                if self.tls_remaining == 0 {
+                    let mut header = [0u8; 5];
+                    self.read_raw(&mut header).await?;
+                    if header[0..3] != [0x17, 0x03, 0x03] {
+                        return Err(ClientError::ProxyHandshakeFailed);
+                    }
+                    self.tls_remaining = u16::from_be_bytes([header[3], header[4]]) as usize;
+                }
+                let le = self.tls_remaining.min(data.len() - s);
+                self.read_raw(&mut data[s..s + le]).await?;
+                self.tls_remaining -= le;
+                s += le;
+            }
+        } else if self.websocket {
+            let mut s = 0;
+            while s < data.len() {
+                if self.ws_remaining == 0 {
+                    self.ws_remaining = self.read_ws_frame(writer).await?;
+                    continue;
+                }
+                let le = self.ws_remaining.min(data.len() - s);
+                self.read_raw(&mut data[s..s + le]).await?;
+                self.ws_remaining -= le;
+                s += le;
+            }
+        } else {
+            self.read_raw(data).await?;
+        }
+        if let Some(decryptor) = &mut self.decryptor {
+            decryptor.apply(data)?;
+        }
+        Ok(())
+    }
+}
+
+/// The sending half of a socket and its state
+struct Writer {
+    /// Socket
+    socket: WriteHalf,
+    /// Encrypts outgoing data if the connection is obfuscated.
+    encryptor: Option<AesCtr>,
+    /// Whether data is wrapped in TLS records
+    tls: bool,
+    /// Whether data is wrapped in WebSocket frames
+    websocket: bool,
+}
+
+impl Writer {
+    /// Write all data, encrypting and wrapping it in TLS records if required.
+    async fn write_all(&mut self, data: &[u8]) -> Result<(), ClientError> {
+        let mut encrypted;
+        let data = match &mut self.encryptor {
+            Some(encryptor) => {
+                encrypted = data.to_vec();
+                encryptor.apply(&mut encrypted)?;
+                &encrypted[..]
+            }
+            None => data,
+        };
+        if self.tls {
+            let mut records = Vec::with_capacity(data.len() + 5);
+            for chunk in data.chunks(MAX_TLS_RECORD_LEN) {
+                records.extend_from_slice(&[0x17, 0x03, 0x03]);
+                records.extend_from_slice(&(chunk.len() as u16).to_be_bytes());
+                records.extend_from_slice(chunk);
+            }
+            self.socket.write_all(&records).await
+        } else if self.websocket {
+            self.socket
+                .write_all(&encode_frame(OPCODE_BINARY, data)?)
+                .await
+        } else {
+            self.socket.write_all(data).await
+        }
+    }
 }
 
 /// Socket wrapper
+///
+/// The socket is split into two halves so receiving does not block sending.
 struct SocketHelper {
-    /// Socket
-    stream: Mutex<Socket>,
+    /// The receiving half
+    reader: Mutex<Reader>,
+    /// The sending half
+    writer: Mutex<Writer>,
     initialized: AtomicBool,
 }
 
 impl SocketHelper {
+    /// Create a new wrapper.
+    fn new(reader: ReadHalf, writer: WriteHalf) -> Self {
+        Self {
+            reader: Mutex::new(Reader {
+                socket: reader,
+                decryptor: None,
+                tls: false,
+                tls_remaining: 0,
+                websocket: false,
+                ws_remaining: 0,
+            }),
+            writer: Mutex::new(Writer {
+                socket: writer,
+                encryptor: None,
+                tls: false,
+                websocket: false,
+            }),
+            initialized: AtomicBool::new(false),
+        }
+    }
+
+    /// Create a new wrapper of any stream.
+    fn from_stream<S>(stream: S) -> Self
+    where
+        S: AsyncRead + AsyncWrite + Unpin + Send + 'static,
+    {
+        let (reader, writer) = tokio::io::split(stream);
+        Self::new(
+            ReadHalf::Stream(Box::new(reader)),
+            WriteHalf::Stream(Box::new(writer)),
+        )
+    }
+
     pub fn is_initialized(&self) -> bool {
         self.initialized.load(Ordering::SeqCst)
     }
 
-    pub async fn init_with(&self, ty: TransportType) -> Result<(), ClientError> {
+    pub async fn init_with(&self, builder: &ClientBuilder) -> Result<(), ClientError> {
+        let ty = builder._transport_type.clone();
+        let secret = builder._mtproxy.as_ref().map(|v| v.secret());
+        if let Some(MtProxySecret::FakeTls(key, domain)) = secret {
+            self.fake_tls_handshake(key, domain).await?;
+        }
+        if let Some(websocket) = &builder._websocket {
+            self.websocket_handshake(websocket).await?;
+        }
+        if builder._obfuscated || secret.is_some() {
+            let tag = protocol_tag(&ty).ok_or(ClientError::UnsupportedTransport)?;
+            let (init, encryptor, decryptor) =
+                init_payload(tag, builder._dc_id, secret.map(|v| v.key()))?;
+            self.reader.lock().await.decryptor = Some(decryptor);
+            let mut writer = self.writer.lock().await;
+            writer.write_all(&init).await?;
+            writer.encryptor = Some(encryptor);
+            self.initialized.store(true, Ordering::SeqCst);
+            return Ok(());
+        }
         match ty {
             TransportType::Abridged => {
-                self.send(&[0xef]).await?;
+                self.send_all(&[0xef]).await?;
                 self.initialized.store(true, Ordering::SeqCst);
                 Ok(())
             }
             TransportType::Intermediate => {
-                self.send(&[0xee, 0xee, 0xee, 0xee]).await?;
+                self.send_all(&[0xee, 0xee, 0xee, 0xee]).await?;
                 self.initialized.store(true, Ordering::SeqCst);
                 Ok(())
             }
             TransportType::PaddedIntermediate => {
-                self.send(&[0xdd, 0xdd, 0xdd, 0xdd]).await?;
+                self.send_all(&[0xdd, 0xdd, 0xdd, 0xdd]).await?;
                 self.initialized.store(true, Ordering::SeqCst);
                 Ok(())
             }
-            TransportType::Full => {
+            TransportType::Full | TransportType::Http => {
                 self.initialized.store(true, Ordering::SeqCst);
                 Ok(())
             }
         }
     }
 
-    pub async fn recv_exact(&self, data: &mut [u8]) -> Result<(), ClientError> {
-        let le = data.len();
-        let mut s = self.recv(data).await?;
-        while s < le {
-            s += self.recv(&mut data[s..]).await?;
+    /// Emulate a TLS handshake with MTProxy. All the following data is wrapped in TLS records.
+    async fn fake_tls_handshake(&self, key: &[u8; 16], domain: &str) -> Result<(), ClientError> {
+        let hello = client_hello(key, domain)?;
+        let mut writer = self.writer.lock().await;
+        writer.socket.write_all(&hello).await?;
+        let mut reader = self.reader.lock().await;
+        let mut response = reader.read_tls_record().await?;
+        response.extend_from_slice(&reader.read_tls_record().await?);
+        response.extend_from_slice(&reader.read_tls_record().await?);
+        if !verify_server_hello(key, &hello, &response)? {
+            return Err(ClientError::ProxyHandshakeFailed);
         }
+        reader.tls = true;
+        writer.socket.write_all(&CHANGE_CIPHER_SPEC).await?;
+        writer.tls = true;
         Ok(())
     }
 
-    pub async fn recv(&self, data: &mut [u8]) -> Result<usize, ClientError> {
-        match self.stream.lock().await.deref_mut() {
-            Socket::TCP(stream) => Ok(stream.read(data).await?),
-            Socket::UDP(stream) => Ok(stream.recv(data).await?),
+    /// Send the WebSocket opening handshake. All the following data is wrapped in frames.
+    async fn websocket_handshake(&self, websocket: &WebSocket) -> Result<(), ClientError> {
+        let (request, key) = handshake_request(websocket)?;
+        let mut writer = self.writer.lock().await;
+        writer.socket.write_all(&request).await?;
+        let mut reader = self.reader.lock().await;
+        // Read byte by byte so no frame after the response is consumed.
+        let mut response = Vec::new();
+        let mut byte = [0u8; 1];
+        while !response.ends_with(b"\r\n\r\n") {
+            if response.len() > 8192 {
+                return Err(ClientError::WebSocketHandshakeFailed);
+            }
+            reader.read_raw(&mut byte).await?;
+            response.push(byte[0]);
+        }
+        if !verify_handshake_response(&response, &key) {
+            return Err(ClientError::WebSocketHandshakeFailed);
         }
+        reader.websocket = true;
+        writer.websocket = true;
+        Ok(())
     }
 
-    pub async fn send(&self, data: &[u8]) -> Result<usize, ClientError> {
-        match self.stream.lock().await.deref_mut() {
-            Socket::TCP(stream) => Ok(stream.write(data).await?),
-            Socket::UDP(stream) => Ok(stream.send(data).await?),
-        }
+    /// Receive exactly `data.len()` bytes.
+    /// A WebSocket ping received meanwhile is answered as soon as it arrives.
+    pub async fn recv_exact(&self, data: &mut [u8]) -> Result<(), ClientError> {
+        let mut reader = self.reader.lock().await;
+        reader.read_exact(data, &self.writer).await
     }
 
     pub async fn send_all(&self, data: &[u8]) -> Result<(), ClientError> {
-        let mut s = self.send(&data).await?;
-        while s < data.len() {
-            s += self.send(&data[s..]).await?;
+        self.writer.lock().await.write_all(data).await
+    }
+}
+
+impl WriteHalf {
+    /// Write all data.
+    async fn write_all(&mut self, data: &[u8]) -> Result<(), ClientError> {
+        match self {
+            WriteHalf::Tcp(stream) => stream.write_all(data).await?,
+            WriteHalf::Stream(stream) => stream.write_all(data).await?,
+            WriteHalf::Udp(stream) => {
+                let mut s = 0;
+                while s < data.len() {
+                    s += stream.send(&data[s..]).await?;
+                }
+            }
         }
         Ok(())
     }
@@ -171,19 +959,15 @@ impl SocketHelper {
 
 impl From<TcpStream> for SocketHelper {
     fn from(stream: TcpStream) -> Self {
-        Self {
-            stream: Mutex::new(Socket::TCP(stream)),
-            initialized: AtomicBool::new(false),
-        }
+        let (reader, writer) = stream.into_split();
+        Self::new(ReadHalf::Tcp(reader), WriteHalf::Tcp(writer))
     }
 }
 
 impl From<UdpSocket> for SocketHelper {
     fn from(stream: UdpSocket) -> Self {
-        Self {
-            stream: Mutex::new(Socket::UDP(stream)),
-            initialized: AtomicBool::new(false),
-        }
+        let stream = Arc::new(stream);
+        Self::new(ReadHalf::Udp(stream.clone()), WriteHalf::Udp(stream))
     }
 }
 
@@ -194,30 +978,47 @@ pub struct Client {
     /// The builder
     builder: ClientBuilder,
     /// the TCP sequence number for this TCP connection: the first packet sent is numbered 0, the next one 1, etc.
-    seq_no: AtomicU32,
+    /// It is locked while a packet is sent so packets are sent in order.
+    seq_no: Mutex<u32>,
+    /// Locked from the allocation of a message id until the message is written,
+    /// so messages reach server in the order of their ids and sequence numbers.
+    send_lock: Mutex<()>,
+    /// Message id generator
+    msg_id: MessageIdGenerator,
+    /// The value of `Host` header in HTTP requests
+    host: String,
+    /// The number of HTTP requests which are not responded yet
+    http_requests: AtomicUsize,
+    /// Pending quick ACK tokens
+    quick_acks: QuickAcks,
+    /// The expected sequence number of the next packet received in [TransportType::Full]
+    recv_seq_no: AtomicU32,
 }
 
 impl Client {
     /// Gen data from payload.
-    fn gen_payload(&self, data: Vec<u8>) -> Vec<u8> {
+    /// * `quick_ack` - Set the most significant bit of the length to request a quick ACK.
+    fn gen_payload(&self, data: Vec<u8>, seq_no: u32, quick_ack: bool) -> Vec<u8> {
+        let flag = if quick_ack { 0x8000_0000u32 } else { 0 };
         match self.builder._transport_type {
             TransportType::Abridged => {
+                let flag = (flag >> 24) as u8;
                 if data.len() >= 508 {
                     let mut payload = Vec::with_capacity(data.len() + 4);
-                    payload.push(0x7fu8);
+                    payload.push(0x7fu8 | flag);
                     payload.extend_from_slice(&((data.len() / 4) as u32).to_le_bytes()[..3]);
                     payload.extend_from_slice(&data);
                     payload
                 } else {
                     let mut payload = Vec::with_capacity(data.len() + 1);
-                    payload.push((data.len() / 4) as u8);
+                    payload.push((data.len() / 4) as u8 | flag);
                     payload.extend_from_slice(&data);
                     payload
                 }
             }
             TransportType::Intermediate => {
                 let mut payload = Vec::with_capacity(data.len() + 4);
-                let le = data.len() as u32;
+                let le = data.len() as u32 | flag;
                 payload.extend_from_slice(&le.to_le_bytes());
                 payload.extend_from_slice(&data);
                 payload
@@ -225,7 +1026,7 @@ impl Client {
             TransportType::PaddedIntermediate => {
                 let random = rand::rngs::StdRng::from_entropy().gen::<u32>() % 16;
                 let mut payload = Vec::with_capacity(data.len() + 4 + random as usize);
-                let le = data.len() as u32 + random;
+                let le = (data.len() as u32 + random) | flag;
                 payload.extend_from_slice(&le.to_le_bytes());
                 payload.extend_from_slice(&data);
                 for _ in 0..random {
@@ -235,129 +1036,453 @@ impl Client {
             }
             TransportType::Full => {
                 let mut payload = Vec::with_capacity(data.len() + 12);
-                let le = data.len() as u32 + 12;
+                let le = (data.len() as u32 + 12) | flag;
                 payload.extend_from_slice(&le.to_le_bytes());
-                payload.extend_from_slice(&(self.seq_no.load(Ordering::SeqCst).to_le_bytes()));
+                payload.extend_from_slice(&seq_no.to_le_bytes());
                 payload.extend_from_slice(&data);
                 payload.extend_from_slice(&(crc32fast::hash(&payload).to_le_bytes()));
                 payload
             }
+            TransportType::Http => {
+                let mut payload = format!(
+                    "POST /api HTTP/1.1\r\nHost: {}\r\nConnection: keep-alive\r\nContent-Length: {}\r\n\r\n",
+                    self.host,
+                    data.len()
+                )
+                .into_bytes();
+                payload.extend_from_slice(&data);
+                payload
+            }
+        }
+    }
+
+    /// Allocate a buffer for a received frame after checking its length.
+    fn alloc_frame(&self, le: usize) -> Result<BytesMut, ClientError> {
+        if le > self.builder._max_frame_len {
+            return Err(ClientError::BadFrameLength(le));
+        }
+        let mut data = BytesMut::with_capacity(le);
+        data.resize(le, 0);
+        Ok(data)
+    }
+
+    /// Receive an HTTP response and returns its body.
+    async fn recv_http(&self) -> Result<BytesMut, ClientError> {
+        let mut byte = [0u8; 1];
+        let deadline = self.recv_head(&mut byte).await?;
+        let mut header = vec![byte[0]];
+        while !header.ends_with(b"\r\n\r\n") {
+            if header.len() > 8192 {
+                return Err(ClientError::BadHttpResponse);
+            }
+            self.recv_body(&mut byte, deadline).await?;
+            header.push(byte[0]);
+        }
+        self.http_requests
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |v| v.checked_sub(1))
+            .ok();
+        let header = String::from_utf8_lossy(&header);
+        let mut lines = header.split("\r\n");
+        let status = lines
+            .next()
+            .and_then(|v| v.split(' ').nth(1))
+            .and_then(|v| v.parse::<i32>().ok())
+            .ok_or(ClientError::BadHttpResponse)?;
+        let le = lines
+            .filter_map(|v| v.split_once(':'))
+            .find(|(k, _)| k.trim().eq_ignore_ascii_case("content-length"))
+            .and_then(|(_, v)| v.trim().parse::<usize>().ok())
+            .ok_or(ClientError::BadHttpResponse)?;
+        let mut data = self.alloc_frame(le)?;
+        self.recv_body(&mut data, deadline).await?;
+        if status != 200 {
+            return Err(ClientError::TransportError((-status).into()));
+        }
+        Ok(data)
+    }
+
+    /// Returns the maximum time to wait before acknowledging received messages.
+    pub(crate) fn ack_delay(&self) -> Duration {
+        self.builder._ack_delay
+    }
+
+    /// Returns the number of message ids which are acknowledged at once.
+    pub(crate) fn ack_batch_size(&self) -> usize {
+        self.builder._ack_batch_size
+    }
+
+    /// Returns the interval between pings.
+    pub(crate) fn ping_interval(&self) -> Duration {
+        self.builder._ping_interval
+    }
+
+    /// Returns the delay after which server closes the connection if no ping is received.
+    pub(crate) fn disconnect_delay(&self) -> Duration {
+        self.builder._disconnect_delay
+    }
+
+    /// Returns the delay before the first reconnection attempt.
+    pub(crate) fn reconnect_delay(&self) -> Duration {
+        self.builder._reconnect_delay
+    }
+
+    /// Returns the maximum delay between reconnection attempts.
+    pub(crate) fn max_reconnect_delay(&self) -> Duration {
+        self.builder._max_reconnect_delay
+    }
+
+    /// Returns the maximum number of failed reconnection attempts before giving up.
+    pub(crate) fn max_reconnect_attempts(&self) -> Option<u32> {
+        self.builder._max_reconnect_attempts
+    }
+
+    /// Returns server public keys used to generate a new authorization key on reconnection.
+    pub(crate) fn public_keys(&self) -> &[RSAPublicKey] {
+        &self.builder._public_keys
+    }
+
+    /// Returns the transport type.
+    pub fn transport_type(&self) -> &TransportType {
+        &self.builder._transport_type
+    }
+
+    /// Returns the number of HTTP requests which are not responded yet.
+    /// Only used by [TransportType::Http].
+    pub fn pending_http_requests(&self) -> usize {
+        self.http_requests.load(Ordering::SeqCst)
+    }
+
+    /// Lock sending until the guard is dropped.
+    /// Message ids and sequence numbers must be allocated while it is held.
+    pub(crate) async fn lock_send(&self) -> MutexGuard<'_, ()> {
+        self.send_lock.lock().await
+    }
+
+    /// Returns the message id generator of this connection.
+    pub fn msg_id_generator(&self) -> &MessageIdGenerator {
+        &self.msg_id
+    }
+
+    /// Initialize the transport if it is not initialized.
+    pub async fn init(&self) -> Result<(), ClientError> {
+        if !self.stream.is_initialized() {
+            self.set_state(ConnectionState::TransportInit);
+            let result = match self.builder._connect_timeout {
+                Some(connect_timeout) => {
+                    tokio::time::timeout(connect_timeout, self.stream.init_with(&self.builder))
+                        .await
+                        .unwrap_or(Err(ClientError::ConnectTimeout))
+                }
+                None => self.stream.init_with(&self.builder).await,
+            };
+            self.set_state(match result {
+                Ok(_) => ConnectionState::Ready,
+                Err(_) => ConnectionState::Closed,
+            });
+            result?;
+        }
+        Ok(())
+    }
+
+    /// Returns a receiver of the connection state.
+    pub fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.builder._state.subscribe()
+    }
+
+    /// Publish a new state. [ConnectionState::Closed] is final.
+    pub(crate) fn set_state(&self, state: ConnectionState) {
+        self.builder._state.send_if_modified(|v| {
+            if *v == state || *v == ConnectionState::Closed {
+                return false;
+            }
+            *v = state;
+            true
+        });
+    }
+
+    /// Publish [ConnectionState::Closed] if the connection can not be used after the error.
+    fn check_error<T>(&self, result: Result<T, ClientError>) -> Result<T, ClientError> {
+        if let Err(e) = &result {
+            if e.is_connection_error() {
+                self.set_state(ConnectionState::Closed);
+            }
+        }
+        result
+    }
+
+    /// Send data
+    /// * `data` - The payload of the transport packet
+    pub async fn send(&self, data: Vec<u8>) -> Result<(), ClientError> {
+        let result = self.send_packet(data, false).await;
+        self.check_error(result)
+    }
+
+    /// Send data in a transport packet.
+    /// * `quick_ack` - Whether a quick ACK is requested
+    async fn send_packet(&self, data: Vec<u8>, quick_ack: bool) -> Result<(), ClientError> {
+        self.init().await?;
+        let mut seq_no = self.seq_no.lock().await;
+        let payload = self.gen_payload(data, *seq_no, quick_ack);
+        self.stream.send_all(&payload).await?;
+        if self.builder._transport_type.is_full() {
+            *seq_no += 1;
+        }
+        if self.builder._transport_type.is_http() {
+            self.http_requests.fetch_add(1, Ordering::SeqCst);
         }
+        Ok(())
     }
 
     /// Send unencrypted data
     /// * `data` - unecrypted data
     pub async fn send_unencrypted<S: Serialize>(&self, data: &S) -> Result<(), ClientError> {
-        if !self.stream.is_initialized() {
-            self.stream
-                .init_with(self.builder._transport_type.clone())
-                .await?;
-        }
-        let mut d = Vec::with_capacity(20);
+        self.send_unencrypted_bytes(&data.serialize_to_bytes())
+            .await
+    }
+
+    /// Send serialized data without encryption.
+    async fn send_unencrypted_bytes(&self, data: &[u8]) -> Result<(), ClientError> {
+        let _guard = self.lock_send().await;
+        let mut d = Vec::with_capacity(20 + data.len());
         d.extend_from_slice(&(0i64).serialize_to_bytes());
-        let message_id = SystemTime::now()
-            .duration_since(UNIX_EPOCH)
-            .unwrap()
-            .as_secs()
-            * (1 << 32);
+        let message_id = self.msg_id.next();
         d.extend_from_slice(&message_id.to_le_bytes());
-        let data = data.serialize_to_bytes();
         d.extend_from_slice(&(data.len() as u32).to_le_bytes());
-        d.extend_from_slice(&data);
-        let payload = self.gen_payload(d);
-        if self.builder._transport_type.is_full() {
-            self.seq_no.fetch_add(1, Ordering::SeqCst);
+        d.extend_from_slice(data);
+        self.send(d).await
+    }
+
+    /// Invoke a function without encryption and wait for its result.
+    /// Only used before an authorization key is created.
+    /// * `function` - The function
+    pub async fn invoke_unencrypted<F: Function>(
+        &self,
+        function: &F,
+    ) -> Result<F::Return, ClientError> {
+        self.send_unencrypted_bytes(&function.serialize_boxed())
+            .await?;
+        Ok(self.recv_unecrypted().await?.deserialize_payload()?)
+    }
+
+    /// Send encrypted message with MTProto 2.0
+    /// * `auth_key` - The authorization key
+    /// * `message` - The message to encrypt
+    pub async fn send_encrypted(
+        &self,
+        auth_key: &AuthKey,
+        message: &DecryptedMessage,
+    ) -> Result<(), ClientError> {
+        let (data, _) = Self::encrypt(auth_key, message)?;
+        self.send(data).await
+    }
+
+    /// Encrypt a message with MTProto 2.0.
+    /// Returns the serialized [EncryptedMessage] and its quick ACK token.
+    fn encrypt(
+        auth_key: &AuthKey,
+        message: &DecryptedMessage,
+    ) -> Result<(Vec<u8>, u32), ClientError> {
+        let (msg_key, encrypted_data, token) = encrypt_message_with_quick_ack(
+            auth_key.as_bytes(),
+            &message.serialize_to_bytes(),
+            Side::Client,
+        )?;
+        let message = EncryptedMessage {
+            auth_key_id: auth_key.id(),
+            msg_key,
+            encrypted_data,
+        };
+        Ok((message.serialize_to_vec(), token))
+    }
+
+    /// Send encrypted message and request a quick ACK.
+    /// Returns a future which resolves when server receives the message.
+    /// [TransportType::Http] does not support quick ACK.
+    /// * `auth_key` - The authorization key
+    /// * `message` - The message to send
+    pub async fn send_encrypted_with_quick_ack(
+        &self,
+        auth_key: &AuthKey,
+        message: &DecryptedMessage,
+    ) -> Result<QuickAck, ClientError> {
+        if self.builder._transport_type.is_http() {
+            return Err(ClientError::UnsupportedTransport);
         }
-        Ok(self.stream.send_all(&payload).await?)
+        let (data, token) = Self::encrypt(auth_key, message)?;
+        // Register before sending because the token may arrive at once.
+        let quick_ack = self.quick_acks.register(token);
+        let result = self.send_packet(data, true).await;
+        self.check_error(result)?;
+        Ok(quick_ack)
+    }
+
+    /// Send a message in the session. Returns the message id.
+    /// * `session` - The session
+    /// * `data` - The message body
+    /// * `content_related` - Whether the message requires an explicit acknowledgment.
+    pub async fn send_in_session<S: Serialize>(
+        &self,
+        session: &Session,
+        data: &S,
+        content_related: bool,
+    ) -> Result<i64, ClientError> {
+        let _guard = self.lock_send().await;
+        let message_id = self.msg_id.next();
+        let message = session.pack(message_id, data.serialize_to_bytes(), content_related);
+        self.send_encrypted(session.auth_key(), &message).await?;
+        Ok(message_id)
     }
 
-    /// Receive data
+    /// Receive data. Quick ACK tokens received meanwhile resolve the matching [QuickAck].
     pub async fn recv(&self) -> Result<BytesMut, ClientError> {
         if !self.stream.is_initialized() {
             return Err(ClientError::NotInitialized);
         }
+        loop {
+            let result = self.recv_packet().await;
+            if let Some(data) = self.check_error(result)? {
+                return Ok(data);
+            }
+        }
+    }
+
+    /// Receive a transport packet. Returns `None` if it is a quick ACK token.
+    async fn recv_packet(&self) -> Result<Option<BytesMut>, ClientError> {
         match self.builder._transport_type {
             TransportType::Abridged => {
                 let mut le = [0u8; 1];
-                self.stream.recv_exact(&mut le).await?;
+                let deadline = self.recv_head(&mut le).await?;
+                if le[0] & 0x80 != 0 {
+                    // The token is sent in big endian.
+                    let mut token = [le[0], 0, 0, 0];
+                    self.recv_body(&mut token[1..], deadline).await?;
+                    self.quick_acks.resolve(u32::from_be_bytes(token));
+                    return Ok(None);
+                }
                 let data = if le[0] == 0x7f {
                     let mut le = [0u8; 3];
-                    self.stream.recv_exact(&mut le).await?;
+                    self.recv_body(&mut le, deadline).await?;
                     let le = [le[0], le[1], le[2], 0];
                     let le = (u32::from_le_bytes(le)) as usize * 4;
-                    let mut data = BytesMut::with_capacity(le);
-                    data.resize(le, 0);
-                    self.stream.recv_exact(&mut data).await?;
+                    let mut data = self.alloc_frame(le)?;
+                    self.recv_body(&mut data, deadline).await?;
                     data
                 } else {
-                    let le = le[0] as usize * 4;
-                    let mut data = BytesMut::with_capacity(le);
-                    data.resize(le, 0);
-                    self.stream.recv_exact(&mut data).await?;
+                    let mut data = self.alloc_frame(le[0] as usize * 4)?;
+                    self.recv_body(&mut data, deadline).await?;
                     data
                 };
                 if data.len() == 4 {
-                    return Err(ClientError::ServerError(i32::deserialize_from_bytes(
-                        &data,
-                    )?));
+                    return Err(ClientError::TransportError(
+                        i32::deserialize_from_bytes(&data)?.into(),
+                    ));
                 }
-                Ok(data)
+                Ok(Some(data))
             }
             TransportType::Intermediate => {
                 let mut le = [0u8; 4];
-                self.stream.recv_exact(&mut le).await?;
-                let le = u32::from_le_bytes(le) as usize;
-                let mut data = BytesMut::with_capacity(le);
-                data.resize(le, 0);
-                self.stream.recv_exact(&mut data).await?;
+                let deadline = self.recv_head(&mut le).await?;
+                let le = u32::from_le_bytes(le);
+                if le & 0x8000_0000 != 0 {
+                    self.quick_acks.resolve(le);
+                    return Ok(None);
+                }
+                let le = le as usize;
+                let mut data = self.alloc_frame(le)?;
+                self.recv_body(&mut data, deadline).await?;
                 if le == 4 {
-                    return Err(ClientError::ServerError(i32::deserialize_from_bytes(
-                        &data,
-                    )?));
+                    return Err(ClientError::TransportError(
+                        i32::deserialize_from_bytes(&data)?.into(),
+                    ));
                 }
-                Ok(data)
+                Ok(Some(data))
             }
             TransportType::PaddedIntermediate => {
                 let mut le = [0u8; 4];
-                self.stream.recv_exact(&mut le).await?;
-                let le = u32::from_le_bytes(le) as usize;
-                let mut data = BytesMut::with_capacity(le);
-                data.resize(le, 0);
-                self.stream.recv_exact(&mut data).await?;
+                let deadline = self.recv_head(&mut le).await?;
+                let le = u32::from_le_bytes(le);
+                if le & 0x8000_0000 != 0 {
+                    self.quick_acks.resolve(le);
+                    return Ok(None);
+                }
+                let le = le as usize;
+                let mut data = self.alloc_frame(le)?;
+                self.recv_body(&mut data, deadline).await?;
                 if le == 4 {
-                    return Err(ClientError::ServerError(i32::deserialize_from_bytes(
-                        &data,
-                    )?));
+                    return Err(ClientError::TransportError(
+                        i32::deserialize_from_bytes(&data)?.into(),
+                    ));
                 }
-                Ok(data)
+                Ok(Some(data))
             }
             TransportType::Full => {
                 let mut h = crc32fast::Hasher::new();
                 let mut le = [0u8; 4];
-                self.stream.recv_exact(&mut le).await?;
+                let deadline = self.recv_head(&mut le).await?;
+                if u32::from_le_bytes(le) & 0x8000_0000 != 0 {
+                    self.quick_acks.resolve(u32::from_le_bytes(le));
+                    return Ok(None);
+                }
                 h.update(&le);
                 let le = u32::from_le_bytes(le) as usize;
+                // Length, sequence number and CRC32 are included.
+                if le < 12 {
+                    return Err(ClientError::BadFrameLength(le));
+                }
+                let mut data = self.alloc_frame(le - 12)?;
                 let mut seq_no = [0u8; 4];
-                self.stream.recv_exact(&mut seq_no).await?;
+                self.recv_body(&mut seq_no, deadline).await?;
                 h.update(&seq_no);
-                let _seq_no = u32::from_le_bytes(seq_no);
-                let mut data = BytesMut::with_capacity(le - 12);
-                data.resize(le - 12, 0);
-                self.stream.recv_exact(&mut data).await?;
+                let seq_no = u32::from_le_bytes(seq_no);
+                self.recv_body(&mut data, deadline).await?;
                 h.update(&data);
                 let mut crc = [0u8; 4];
-                self.stream.recv_exact(&mut crc).await?;
+                self.recv_body(&mut crc, deadline).await?;
                 let crc = u32::from_le_bytes(crc);
                 if crc != h.finalize() {
                     return Err(ClientError::Crc32CheckFailed);
                 }
-                if le == 4 {
-                    return Err(ClientError::ServerError(i32::deserialize_from_bytes(
-                        &data,
-                    )?));
+                let expected = self.recv_seq_no.fetch_add(1, Ordering::SeqCst);
+                if seq_no != expected {
+                    return Err(ClientError::SeqNoMismatched {
+                        expected,
+                        received: seq_no,
+                    });
                 }
-                Ok(data)
+                if data.len() == 4 {
+                    return Err(ClientError::TransportError(
+                        i32::deserialize_from_bytes(&data)?.into(),
+                    ));
+                }
+                Ok(Some(data))
             }
+            TransportType::Http => self.recv_http().await.map(Some),
+        }
+    }
+
+    /// Receive the first bytes of a frame within [ClientBuilder::idle_timeout].
+    /// Returns the deadline of the rest of the frame.
+    async fn recv_head(&self, data: &mut [u8]) -> Result<Option<Instant>, ClientError> {
+        match self.builder._idle_timeout {
+            Some(idle_timeout) => tokio::time::timeout(idle_timeout, self.stream.recv_exact(data))
+                .await
+                .map_err(|_| ClientError::IdleTimeout)??,
+            None => self.stream.recv_exact(data).await?,
+        }
+        Ok(self.builder._read_timeout.map(|v| Instant::now() + v))
+    }
+
+    /// Receive the rest of a frame before the deadline.
+    async fn recv_body(
+        &self,
+        data: &mut [u8],
+        deadline: Option<Instant>,
+    ) -> Result<(), ClientError> {
+        match deadline {
+            Some(deadline) => tokio::time::timeout_at(deadline, self.stream.recv_exact(data))
+                .await
+                .map_err(|_| ClientError::ReadTimeout)?,
+            None => self.stream.recv_exact(data).await,
         }
     }
 
@@ -367,4 +1492,398 @@ impl Client {
             &self.recv().await?,
         )?)
     }
+
+    /// Receive encrypted message and decrypt it with MTProto 2.0
+    /// * `auth_key` - The authorization key
+    pub async fn recv_encrypted(
+        &self,
+        auth_key: &AuthKey,
+    ) -> Result<DecryptedMessage, ClientError> {
+        let message = EncryptedMessage::deserialize_from_bytes(&self.recv().await?)?;
+        if message.auth_key_id != auth_key.id() {
+            return Err(ClientError::AuthKeyIdMismatched);
+        }
+        let data = decrypt_message(
+            auth_key.as_bytes(),
+            &message.msg_key,
+            &message.encrypted_data,
+            Side::Server,
+        )?;
+        let message = DecryptedMessage::deserialize_from_bytes(&data)?;
+        let padding = data.len() - 32 - message.payload.len();
+        if !(12..=1024).contains(&padding) {
+            return Err(ClientError::BadPadding);
+        }
+        Ok(message)
+    }
+
+    /// Receive a message in the session. The session state is updated from service messages.
+    /// Containers and `gzip_packed` objects are unpacked recursively.
+    /// * `session` - The session
+    pub async fn recv_in_session(&self, session: &Session) -> Result<Vec<message>, ClientError> {
+        let message = self.recv_encrypted(session.auth_key()).await?;
+        session.handle_message(&message)
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_http_transport() {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        assert_eq!(
+            String::from_utf8(request).unwrap(),
+            format!(
+                "POST /api HTTP/1.1\r\nHost: {address}\r\nConnection: keep-alive\r\n\
+                 Content-Length: 4\r\n\r\n"
+            )
+        );
+        let mut body = [0u8; 4];
+        stream.read_exact(&mut body).await.unwrap();
+        assert_eq!(&body, b"ping");
+        stream
+            .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 4\r\n\r\npong")
+            .await
+            .unwrap();
+        stream
+            .write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n")
+            .await
+            .unwrap();
+    });
+    let client = ClientBuilder::new()
+        .transport_type(TransportType::Http)
+        .build(address)
+        .await
+        .unwrap();
+    client.send(b"ping".to_vec()).await.unwrap();
+    assert_eq!(client.pending_http_requests(), 1);
+    assert_eq!(&client.recv().await.unwrap()[..], b"pong");
+    assert_eq!(client.pending_http_requests(), 0);
+    assert!(matches!(
+        client.recv().await,
+        Err(ClientError::TransportError(
+            super::TransportError::AuthKeyNotFound
+        ))
+    ));
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_build_with_stream() {
+    let (stream, mut server) = tokio::io::duplex(64);
+    let client = ClientBuilder::new()
+        .transport_type(TransportType::Intermediate)
+        .build_with_stream(stream)
+        .unwrap();
+    client.send(b"0123".to_vec()).await.unwrap();
+    let mut data = [0u8; 12];
+    server.read_exact(&mut data).await.unwrap();
+    assert_eq!(&data, b"\xee\xee\xee\xee\x04\x00\x00\x000123");
+    server.write_all(b"\x08\x00\x00\x0076543210").await.unwrap();
+    assert_eq!(&client.recv().await.unwrap()[..], b"76543210");
+    assert!(matches!(
+        ClientBuilder::new()
+            .use_udp(true)
+            .build_with_stream(tokio::io::duplex(64).0),
+        Err(ClientError::UnsupportedTransport)
+    ));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_concurrent_send_recv() {
+    let (stream, mut server) = tokio::io::duplex(1024);
+    let client = Arc::new(
+        ClientBuilder::new()
+            .transport_type(TransportType::Intermediate)
+            .build_with_stream(stream)
+            .unwrap(),
+    );
+    client.init().await.unwrap();
+    let recv = tokio::spawn({
+        let client = client.clone();
+        async move { client.recv().await }
+    });
+    // The pending receive must not block sending.
+    for _ in 0..4 {
+        tokio::time::timeout(Duration::from_secs(1), client.send(b"0123".to_vec()))
+            .await
+            .unwrap()
+            .unwrap();
+    }
+    let mut data = [0u8; 36];
+    server.read_exact(&mut data).await.unwrap();
+    server.write_all(b"\x08\x00\x00\x0076543210").await.unwrap();
+    assert_eq!(&recv.await.unwrap().unwrap()[..], b"76543210");
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_quick_ack() {
+    let (stream, mut server) = tokio::io::duplex(4096);
+    let client = ClientBuilder::new()
+        .transport_type(TransportType::Intermediate)
+        .build_with_stream(stream)
+        .unwrap();
+    let auth_key = AuthKey::new([7; 256]);
+    let session = Session::new(auth_key.clone(), 0);
+    let message = session.pack(client.msg_id_generator().next(), BytesMut::new(), true);
+    let quick_ack = client
+        .send_encrypted_with_quick_ack(&auth_key, &message)
+        .await
+        .unwrap();
+    let mut data = [0u8; 8];
+    server.read_exact(&mut data).await.unwrap();
+    let le = u32::from_le_bytes(data[4..8].try_into().unwrap());
+    assert_ne!(le & 0x8000_0000, 0);
+    let mut data = vec![0u8; (le & 0x7fff_ffff) as usize];
+    server.read_exact(&mut data).await.unwrap();
+    let message = EncryptedMessage::deserialize_from_bytes(&data).unwrap();
+    let plaintext = decrypt_message(
+        auth_key.as_bytes(),
+        &message.msg_key,
+        &message.encrypted_data,
+        Side::Client,
+    )
+    .unwrap();
+    let token = crate::aes::quick_ack_token(auth_key.as_bytes(), &plaintext, Side::Client);
+    server.write_all(&token.to_le_bytes()).await.unwrap();
+    server.write_all(b"\x08\x00\x00\x0076543210").await.unwrap();
+    assert_eq!(&client.recv().await.unwrap()[..], b"76543210");
+    quick_ack.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_full_seq_no() {
+    let full_frame = |seq_no: u32, data: &[u8]| {
+        let mut frame = (data.len() as u32 + 12).to_le_bytes().to_vec();
+        frame.extend_from_slice(&seq_no.to_le_bytes());
+        frame.extend_from_slice(data);
+        frame.extend_from_slice(&crc32fast::hash(&frame).to_le_bytes());
+        frame
+    };
+    let (stream, mut server) = tokio::io::duplex(1024);
+    let client = ClientBuilder::new().build_with_stream(stream).unwrap();
+    client.init().await.unwrap();
+    server.write_all(&full_frame(0, b"01234567")).await.unwrap();
+    server.write_all(&full_frame(0, b"01234567")).await.unwrap();
+    assert_eq!(&client.recv().await.unwrap()[..], b"01234567");
+    assert!(matches!(
+        client.recv().await,
+        Err(ClientError::SeqNoMismatched {
+            expected: 1,
+            received: 0
+        })
+    ));
+
+    let (stream, mut server) = tokio::io::duplex(1024);
+    let client = ClientBuilder::new()
+        .max_frame_len(16)
+        .build_with_stream(stream)
+        .unwrap();
+    client.init().await.unwrap();
+    server.write_all(&8u32.to_le_bytes()).await.unwrap();
+    assert!(matches!(
+        client.recv().await,
+        Err(ClientError::BadFrameLength(8))
+    ));
+    server.write_all(&full_frame(0, &[0; 32])).await.unwrap();
+    assert!(matches!(
+        client.recv().await,
+        Err(ClientError::BadFrameLength(32))
+    ));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_connection_state() {
+    let (stream, server) = tokio::io::duplex(64);
+    let client = ClientBuilder::new()
+        .transport_type(TransportType::Abridged)
+        .build_with_stream(stream)
+        .unwrap();
+    let mut state = client.state();
+    assert_eq!(*state.borrow_and_update(), ConnectionState::Connecting);
+    client.init().await.unwrap();
+    assert_eq!(*state.borrow_and_update(), ConnectionState::Ready);
+    drop(server);
+    assert!(matches!(
+        tokio::time::timeout(Duration::from_secs(1), client.recv())
+            .await
+            .unwrap(),
+        Err(ClientError::Disconnected)
+    ));
+    state.changed().await.unwrap();
+    assert_eq!(*state.borrow(), ConnectionState::Closed);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_timeouts() {
+    let (stream, mut server) = tokio::io::duplex(1024);
+    let client = ClientBuilder::new()
+        .transport_type(TransportType::Intermediate)
+        .idle_timeout(Duration::from_millis(50))
+        .read_timeout(Duration::from_millis(50))
+        .build_with_stream(stream)
+        .unwrap();
+    client.init().await.unwrap();
+    assert!(matches!(client.recv().await, Err(ClientError::IdleTimeout)));
+    // Only the length of the frame is received.
+    server.write_all(&8u32.to_le_bytes()).await.unwrap();
+    assert!(matches!(client.recv().await, Err(ClientError::ReadTimeout)));
+    assert_eq!(*client.state().borrow(), ConnectionState::Closed);
+
+    // A proxy which never answers
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move { listener.accept().await.unwrap() });
+    assert!(matches!(
+        ClientBuilder::new()
+            .proxy(Proxy::socks5(address.to_string()))
+            .connect_timeout(Duration::from_millis(50))
+            .build("149.154.167.50:443")
+            .await,
+        Err(ClientError::ConnectTimeout)
+    ));
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_proxy_target() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        stream.write_all(&[0x05, 0x00]).await.unwrap();
+        // The host name is sent to the proxy unresolved.
+        let mut request = [0u8; 18];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        let mut tag = [0u8; 1];
+        stream.read_exact(&mut tag).await.unwrap();
+        assert_eq!(tag[0], 0xef);
+    });
+    let client = ClientBuilder::new()
+        .transport_type(TransportType::Abridged)
+        .proxy(Proxy::socks5(address.to_string()))
+        .proxy_target("example.com:443")
+        .build(("127.0.0.1", 443))
+        .await
+        .unwrap();
+    assert_eq!(client.host, "example.com:443");
+    client.init().await.unwrap();
+    server.await.unwrap();
+}
+
+#[test]
+fn test_interleave() {
+    let endpoints = [
+        "149.154.167.50:443",
+        "149.154.167.51:443",
+        "[2001:67c:4e8:f002::a]:443",
+        "149.154.167.91:443",
+        "[2001:67c:4e8:f002::b]:443",
+    ]
+    .map(|v| v.parse::<SocketAddr>().unwrap());
+    assert_eq!(
+        interleave(&endpoints),
+        [
+            endpoints[2],
+            endpoints[0],
+            endpoints[4],
+            endpoints[1],
+            endpoints[3]
+        ]
+    );
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_build_any() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut tag = [0u8; 1];
+        stream.read_exact(&mut tag).await.unwrap();
+        assert_eq!(tag[0], 0xef);
+    });
+    // Nothing listens on the IPv6 endpoint, so the IPv4 one is tried without waiting.
+    let builder = ClientBuilder::new()
+        .transport_type(TransportType::Abridged)
+        .happy_eyeballs_delay(Duration::from_secs(60));
+    let state = builder.state();
+    let client = tokio::time::timeout(
+        Duration::from_secs(5),
+        builder.build_any(&[address, "[::1]:1".parse().unwrap()]),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert_eq!(client.host, address.to_string());
+    assert_eq!(*state.borrow(), ConnectionState::Ready);
+    assert_eq!(*client.state().borrow(), ConnectionState::Ready);
+    server.await.unwrap();
+    assert!(ClientBuilder::new().build_any(&[]).await.is_err());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_build_any_stagger() {
+    // Connections to a listener whose accept queue is full hang.
+    let socket = tokio::net::TcpSocket::new_v4().unwrap();
+    socket.bind("127.0.0.1:0".parse().unwrap()).unwrap();
+    let hanging = socket.listen(0).unwrap();
+    let hanging_address = hanging.local_addr().unwrap();
+    let mut queued = Vec::new();
+    for _ in 0..2 {
+        if let Ok(Ok(stream)) = tokio::time::timeout(
+            Duration::from_millis(100),
+            TcpStream::connect(hanging_address),
+        )
+        .await
+        {
+            queued.push(stream);
+        }
+    }
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut tag = [0u8; 1];
+        stream.read_exact(&mut tag).await.unwrap();
+    });
+    let delay = Duration::from_millis(100);
+    let start = std::time::Instant::now();
+    let client = tokio::time::timeout(
+        Duration::from_secs(5),
+        ClientBuilder::new()
+            .transport_type(TransportType::Abridged)
+            .happy_eyeballs_delay(delay)
+            .build_any(&[hanging_address, address]),
+    )
+    .await
+    .unwrap()
+    .unwrap();
+    assert!(start.elapsed() >= delay);
+    assert_eq!(client.host, address.to_string());
+    server.await.unwrap();
 }
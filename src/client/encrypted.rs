@@ -0,0 +1,1249 @@
+use super::ack::AckManager;
+use super::{Client, ClientError, ConnectionState, QuickAck, Session, TransportError};
+use crate::objects::base::DecryptedMessage;
+use crate::objects::mtproto_api::constructors::{
+    message, msg_container, msgs_ack, pong, rpc_error, rpc_result, BadMsgCode,
+};
+use crate::objects::mtproto_api::functions::{http_wait, ping_delay_disconnect};
+use crate::objects::mtproto_api::types::BadMsgNotification;
+use crate::objects::traits::{Deserialize, Function, OptDeserialize, Serialize, TypeId};
+use bytes::BytesMut;
+use rand::{Rng, SeedableRng};
+use std::collections::HashMap;
+use std::future::Future;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::sync::{oneshot, Notify};
+use tokio::task::{AbortHandle, JoinHandle};
+
+/// The maximum number of times a request is resent after it is ignored by server.
+const MAX_RESEND: usize = 5;
+
+/// Milliseconds which a long poll of HTTP transport is kept open by server.
+const HTTP_MAX_WAIT: i32 = 25000;
+
+/// Seconds which a message that is not content-related is kept to be resent if server rejects it.
+/// Server does not acknowledge such messages, and it rejects message ids older than this anyway.
+const SERVICE_MESSAGE_LIFETIME: i64 = 300;
+
+/// The sender of a pending request
+type PendingSender = oneshot::Sender<Result<BytesMut, ClientError>>;
+
+/// Requests waiting for their results, keyed by message id.
+type PendingMap = Arc<Mutex<HashMap<i64, PendingSender>>>;
+
+/// Messages which are sent but not acknowledged, keyed by message id.
+/// Messages which are not content-related are kept for [SERVICE_MESSAGE_LIFETIME].
+type SentMap = Arc<Mutex<HashMap<i64, SentMessage>>>;
+
+/// A message which is sent but not acknowledged by server yet
+struct SentMessage {
+    /// Serialized message body
+    body: BytesMut,
+    /// Whether the message requires an explicit acknowledgment
+    content_related: bool,
+    /// The id of the container which the message is sent in
+    container_id: Option<i64>,
+    /// The number of times the message is resent
+    attempts: usize,
+}
+
+/// Shared state of [EncryptedClient]
+struct Inner {
+    /// The connection
+    client: Client,
+    /// The session. It is shared with the connections which resume it.
+    session: Arc<Session>,
+    /// Requests waiting for their results. They are kept when the connection is resumed.
+    pending: PendingMap,
+    /// Received messages waiting for acknowledgment
+    acks: AckManager,
+    /// Held while acknowledgments are flushed. Set once they are handed over to the connection
+    /// which resumes the session, and they are not flushed here any more then.
+    acks_handed_over: tokio::sync::Mutex<bool>,
+    /// Messages which are sent but not acknowledged. They are resent when the connection is resumed.
+    sent: SentMap,
+    /// The round-trip time measured by the last ping
+    rtt: Mutex<Option<Duration>>,
+    /// Whether the connection is considered dead
+    closed: AtomicBool,
+    /// Notified when the connection is considered dead
+    closed_notify: Notify,
+    /// The transport error which closed the connection
+    transport_error: Mutex<Option<TransportError>>,
+    /// Whether the connection is resumed by [ReconnectingClient](super::ReconnectingClient)
+    /// after it is closed. Pending requests are kept then.
+    supervised: bool,
+    /// The background tasks. They are aborted when an unsupervised connection is closed.
+    tasks: Mutex<Vec<AbortHandle>>,
+}
+
+impl Inner {
+    /// Handle an unpacked message received from server.
+    async fn handle_message(&self, message: message) -> Result<(), ClientError> {
+        if message.body_type_id() == Some(msgs_ack::type_id2()) {
+            let ack = msgs_ack::deserialize_from_bytes(&message.body[4..])?;
+            let mut sent = self.sent.lock().unwrap();
+            for id in ack.msg_ids.iter() {
+                sent.remove(id);
+            }
+        } else if let Some(notification) =
+            BadMsgNotification::opt_deserialize_from_bytes(&message.body)?
+        {
+            self.handle_bad_msg(message.msg_id, &notification).await;
+        } else if message.body_type_id() == Some(pong::type_id2()) {
+            // `pong` is the result of `ping` without `rpc_result`.
+            let pong = pong::deserialize_from_bytes(&message.body[4..])?;
+            self.complete(pong.msg_id, Ok(message.body));
+        } else if message.body_type_id() == Some(rpc_result::type_id2()) {
+            let result = rpc_result::deserialize_from_bytes(&message.body[4..])?;
+            let body = if result
+                .result
+                .starts_with(&rpc_error::type_id2().to_le_bytes())
+            {
+                Err(ClientError::RpcError(rpc_error::deserialize_from_bytes(
+                    &result.result[4..],
+                )?))
+            } else {
+                Ok(result.result)
+            };
+            self.complete(result.req_msg_id, body);
+        }
+        Ok(())
+    }
+
+    /// Complete a pending request.
+    /// * `req_msg_id` - The message id of the request
+    /// * `result` - The serialized result
+    fn complete(&self, req_msg_id: i64, result: Result<BytesMut, ClientError>) {
+        self.sent.lock().unwrap().remove(&req_msg_id);
+        let sender = self.pending.lock().unwrap().remove(&req_msg_id);
+        if let Some(sender) = sender {
+            sender.send(result).ok();
+        }
+    }
+
+    /// Consider the connection dead. Pending requests are failed unless the connection is supervised.
+    fn close(&self) {
+        self.closed.store(true, Ordering::SeqCst);
+        if !self.supervised {
+            self.client.set_state(ConnectionState::Closed);
+            self.fail_pending();
+            for task in std::mem::take(&mut *self.tasks.lock().unwrap()) {
+                task.abort();
+            }
+        }
+        self.closed_notify.notify_waiters();
+    }
+
+    /// Fail all pending requests with the transport error which closed the connection,
+    /// or [ClientError::Disconnected] if there is none.
+    fn fail_pending(&self) {
+        let error = *self.transport_error.lock().unwrap();
+        let pending = std::mem::take(&mut *self.pending.lock().unwrap());
+        match error {
+            Some(error) => {
+                for (_, sender) in pending {
+                    sender.send(Err(ClientError::TransportError(error))).ok();
+                }
+            }
+            // Dropping the senders wakes up all pending requests.
+            None => drop(pending),
+        }
+    }
+
+    /// Fix the session state according to the notification and resend the ignored messages.
+    /// * `msg_id` - The message id of the notification
+    /// * `notification` - The notification
+    async fn handle_bad_msg(&self, msg_id: i64, notification: &BadMsgNotification) {
+        let code = notification.error_code();
+        let restart = match notification {
+            BadMsgNotification::Bad_server_salt(v) => {
+                self.session.set_server_salt(v.new_server_salt);
+                false
+            }
+            BadMsgNotification::Bad_msg_notification(_) => match code {
+                // Ids following a clock moved back would be lower than the ones sent before.
+                BadMsgCode::MsgIdTooLow | BadMsgCode::MsgIdTooHigh => {
+                    self.client.msg_id_generator().correct_time(msg_id)
+                }
+                BadMsgCode::SeqNoTooLow | BadMsgCode::SeqNoTooHigh => true,
+                _ => false,
+            },
+        };
+        let ids = if restart {
+            // Start over in a new session. Results of the other messages
+            // in the old session would be lost, so all of them are resent.
+            self.session.reset();
+            self.client.msg_id_generator().reset();
+            self.sent.lock().unwrap().keys().copied().collect()
+        } else {
+            vec![notification.bad_msg_id()]
+        };
+        for id in ids {
+            self.resend(id, code).await;
+        }
+    }
+
+    /// Resend a message, or the messages in a container, under new message ids.
+    /// * `msg_id` - The message id of the message or the container
+    /// * `code` - The reason why the message is ignored
+    async fn resend(&self, msg_id: i64, code: BadMsgCode) {
+        let ids = {
+            let sent = self.sent.lock().unwrap();
+            if sent.contains_key(&msg_id) {
+                vec![msg_id]
+            } else {
+                sent.iter()
+                    .filter(|(_, v)| v.container_id == Some(msg_id))
+                    .map(|(k, _)| *k)
+                    .collect()
+            }
+        };
+        for id in ids {
+            self.resend_message(id, Some(code)).await;
+        }
+    }
+
+    /// Resend all the messages which are not acknowledged, e.g. after the connection is resumed.
+    async fn resend_all(&self) {
+        let ids = self
+            .sent
+            .lock()
+            .unwrap()
+            .keys()
+            .copied()
+            .collect::<Vec<_>>();
+        for id in ids {
+            self.resend_message(id, None).await;
+        }
+    }
+
+    /// Resend a message under a new message id.
+    /// * `id` - The message id
+    /// * `code` - The reason why the message is ignored by server, if it is.
+    async fn resend_message(&self, id: i64, code: Option<BadMsgCode>) {
+        let Some(mut sent) = self.sent.lock().unwrap().remove(&id) else {
+            return;
+        };
+        if sent.body.starts_with(&msgs_ack::type_id2().to_le_bytes()) {
+            // The acknowledgments are sent again with the next batch.
+            if let Ok(ack) = msgs_ack::deserialize_from_bytes(&sent.body[4..]) {
+                self.acks.push(&ack.msg_ids);
+            }
+            return;
+        }
+        let sender = self.pending.lock().unwrap().remove(&id);
+        if let Some(code) = code {
+            sent.attempts += 1;
+            if sent.attempts > MAX_RESEND {
+                if let Some(sender) = sender {
+                    sender.send(Err(ClientError::BadMessage(code))).ok();
+                }
+                return;
+            }
+            match code {
+                BadMsgCode::EvenSeqNoExpected => sent.content_related = false,
+                BadMsgCode::OddSeqNoExpected => sent.content_related = true,
+                _ => {}
+            }
+        }
+        sent.container_id = None;
+        {
+            let _guard = self.client.lock_send().await;
+            let new_id = self.client.msg_id_generator().next();
+            let message = self
+                .session
+                .pack(new_id, sent.body.clone(), sent.content_related);
+            if let Some(sender) = sender {
+                self.pending.lock().unwrap().insert(new_id, sender);
+            }
+            self.keep_sent(new_id, sent);
+            // A broken connection is detected by the reader.
+            self.client
+                .send_encrypted(self.session.auth_key(), &message)
+                .await
+                .ok();
+        }
+    }
+
+    /// Receive messages until the connection is broken.
+    async fn read_loop(&self) {
+        loop {
+            if self.client.transport_type().is_http() && self.client.pending_http_requests() == 0 {
+                // Keep a request open so that server can push messages.
+                let wait = Box::new(http_wait {
+                    max_delay: 0,
+                    wait_after: 0,
+                    max_wait: HTTP_MAX_WAIT,
+                });
+                if let Err(ClientError::TokioError(_)) =
+                    self.send_service(wait.serialize_to_bytes()).await
+                {
+                    break;
+                }
+            }
+            match self.client.recv_in_session(&self.session).await {
+                Ok(messages) => {
+                    let ids = messages
+                        .iter()
+                        .filter(|v| v.is_content_related())
+                        .map(|v| v.msg_id)
+                        .collect::<Vec<_>>();
+                    self.acks.push(&ids);
+                    for message in messages {
+                        self.handle_message(message).await.ok();
+                    }
+                }
+                Err(ClientError::TransportError(error)) => {
+                    *self.transport_error.lock().unwrap() = Some(error);
+                    break;
+                }
+                Err(e) if e.is_connection_error() => break,
+                Err(ClientError::NotInitialized) => break,
+                Err(_) => {}
+            }
+        }
+        self.close();
+    }
+
+    /// Send pending acknowledgments in a standalone `msgs_ack`.
+    async fn flush_acks(&self) -> Result<(), ClientError> {
+        let handed_over = self.acks_handed_over.lock().await;
+        if *handed_over {
+            return Err(ClientError::Disconnected);
+        }
+        let ids = self.acks.take();
+        if ids.is_empty() {
+            return Ok(());
+        }
+        let ack = Box::new(msgs_ack {
+            msg_ids: Box::new(ids.clone()),
+        });
+        if let Err(e) = self.send_service(ack.serialize_to_bytes()).await {
+            self.acks.push(&ids);
+            return Err(e);
+        }
+        Ok(())
+    }
+
+    /// Stop flushing acknowledgments and take all the pending ones,
+    /// e.g. to send them over the connection which resumes the session.
+    /// A flush in progress is waited for, so no id is lost or sent twice.
+    async fn hand_over_acks(&self) -> Vec<i64> {
+        let mut handed_over = self.acks_handed_over.lock().await;
+        *handed_over = true;
+        let mut ids = Vec::new();
+        loop {
+            let batch = self.acks.take();
+            if batch.is_empty() {
+                return ids;
+            }
+            ids.extend(batch);
+        }
+    }
+
+    /// Send acknowledgments when the delay expires or the batch is full.
+    async fn ack_loop(&self) {
+        loop {
+            self.acks.arrived().await;
+            tokio::time::timeout(self.client.ack_delay(), self.acks.full())
+                .await
+                .ok();
+            if self.flush_acks().await.is_err() {
+                break;
+            }
+        }
+    }
+
+    /// Send `ping_delay_disconnect` periodically and measure the round-trip time.
+    /// The connection is closed if `pong` is not received before server would disconnect,
+    /// that is within the disconnect delay minus the ping interval.
+    async fn keepalive_loop(&self) {
+        let interval = self.client.ping_interval();
+        let disconnect_delay = self.client.disconnect_delay();
+        let pong_timeout = match disconnect_delay.saturating_sub(interval) {
+            margin if margin.is_zero() => interval,
+            margin => margin,
+        };
+        let mut random = rand::rngs::StdRng::from_entropy();
+        loop {
+            tokio::time::sleep(interval).await;
+            let ping = ping_delay_disconnect {
+                ping_id: random.gen(),
+                disconnect_delay: disconnect_delay.as_secs() as i32,
+            };
+            let start = Instant::now();
+            match tokio::time::timeout(pong_timeout, self.invoke(&ping)).await {
+                Ok(Ok(pong)) if pong.ping_id == ping.ping_id => {
+                    *self.rtt.lock().unwrap() = Some(start.elapsed());
+                }
+                _ => {
+                    self.forget(&ping.serialize_boxed());
+                    self.close();
+                    break;
+                }
+            }
+        }
+    }
+
+    /// Stop waiting for a request, which may have been resent under another message id.
+    /// * `body` - Serialized request
+    fn forget(&self, body: &[u8]) {
+        let mut sent = self.sent.lock().unwrap();
+        let ids = sent
+            .iter()
+            .filter(|(_, v)| v.body == body)
+            .map(|(k, _)| *k)
+            .collect::<Vec<_>>();
+        let mut pending = self.pending.lock().unwrap();
+        for id in ids {
+            sent.remove(&id);
+            pending.remove(&id);
+        }
+    }
+
+    /// Invoke a function and wait for its result.
+    async fn invoke<F: Function>(&self, function: &F) -> Result<F::Return, ClientError> {
+        let (_, receiver) = self.send_request(function, false).await?;
+        let result = receiver.await.map_err(|_| ClientError::Disconnected)??;
+        Ok(F::Return::deserialize_from_bytes(&result)?)
+    }
+
+    /// Send a request. Returns the receiver of its result.
+    /// * `quick_ack` - Whether a quick ACK is requested
+    async fn send_request<F: Function>(
+        &self,
+        function: &F,
+        quick_ack: bool,
+    ) -> Result<
+        (
+            Option<QuickAck>,
+            oneshot::Receiver<Result<BytesMut, ClientError>>,
+        ),
+        ClientError,
+    > {
+        if self.closed.load(Ordering::SeqCst) {
+            return Err(ClientError::Disconnected);
+        }
+        let guard = self.client.lock_send().await;
+        let (message_id, message, acks) = self.pack_request(function.serialize_boxed());
+        let (sender, receiver) = oneshot::channel();
+        self.pending.lock().unwrap().insert(message_id, sender);
+        let auth_key = self.session.auth_key();
+        let result = if quick_ack {
+            self.client
+                .send_encrypted_with_quick_ack(auth_key, &message)
+                .await
+                .map(Some)
+        } else {
+            self.client
+                .send_encrypted(auth_key, &message)
+                .await
+                .map(|_| None)
+        };
+        drop(guard);
+        match result {
+            Ok(quick_ack) => Ok((quick_ack, receiver)),
+            // The request is resent when the connection is resumed.
+            Err(e) if self.supervised && e.is_connection_error() => Ok((None, receiver)),
+            Err(e) => {
+                self.pending.lock().unwrap().remove(&message_id);
+                let mut sent = self.sent.lock().unwrap();
+                if let Some(container_id) = sent.remove(&message_id).and_then(|v| v.container_id) {
+                    sent.retain(|_, v| v.container_id != Some(container_id));
+                }
+                drop(sent);
+                self.acks.push(&acks);
+                Err(e)
+            }
+        }
+    }
+
+    /// Pack a request and keep it until it is acknowledged.
+    /// Pending acknowledgments are put into a container with the request.
+    /// Must be called with [Client::lock_send] held until the message is sent.
+    /// Returns the message id of the request, the message to send and the acknowledged ids.
+    fn pack_request(&self, body: BytesMut) -> (i64, DecryptedMessage, Vec<i64>) {
+        let msg_id = self.client.msg_id_generator();
+        let ids = self.acks.take();
+        let mut sent = SentMessage {
+            body: body.clone(),
+            content_related: true,
+            container_id: None,
+            attempts: 0,
+        };
+        if ids.is_empty() {
+            let message_id = msg_id.next();
+            self.keep_sent(message_id, sent);
+            return (message_id, self.session.pack(message_id, body, true), ids);
+        }
+        let ack_id = msg_id.next();
+        let ack_body = Box::new(msgs_ack {
+            msg_ids: Box::new(ids.clone()),
+        })
+        .serialize_to_bytes();
+        let ack = message {
+            msg_id: ack_id,
+            seqno: self.session.next_seq_no(false),
+            body: ack_body.clone(),
+        };
+        let request = message {
+            msg_id: msg_id.next(),
+            seqno: self.session.next_seq_no(true),
+            body,
+        };
+        let message_id = request.msg_id;
+        let container = Box::new(msg_container {
+            messages: vec![ack, request],
+        });
+        let container_id = msg_id.next();
+        let container = self
+            .session
+            .pack(container_id, container.serialize_to_bytes(), false);
+        sent.container_id = Some(container_id);
+        self.keep_sent(message_id, sent);
+        // Kept so that the acknowledgments are sent again if server rejects the container.
+        self.keep_sent(
+            ack_id,
+            SentMessage {
+                body: ack_body,
+                content_related: false,
+                container_id: Some(container_id),
+                attempts: 0,
+            },
+        );
+        (message_id, container, ids)
+    }
+
+    /// Keep a sent message until it is acknowledged,
+    /// and drop the messages which are not content-related and too old to be rejected.
+    /// * `message_id` - The message id
+    /// * `message` - The message
+    fn keep_sent(&self, message_id: i64, message: SentMessage) {
+        let mut sent = self.sent.lock().unwrap();
+        sent.retain(|id, v| {
+            v.content_related || (message_id - id) >> 32 < SERVICE_MESSAGE_LIFETIME
+        });
+        sent.insert(message_id, message);
+    }
+
+    /// Send a message which is not content-related, e.g. `msgs_ack`.
+    /// It is kept for a while so that it is resent if server rejects it.
+    /// * `body` - Serialized message body
+    async fn send_service(&self, body: BytesMut) -> Result<(), ClientError> {
+        let _guard = self.client.lock_send().await;
+        let message_id = self.client.msg_id_generator().next();
+        let message = self.session.pack(message_id, body.clone(), false);
+        self.keep_sent(
+            message_id,
+            SentMessage {
+                body,
+                content_related: false,
+                container_id: None,
+                attempts: 0,
+            },
+        );
+        let result = self
+            .client
+            .send_encrypted(self.session.auth_key(), &message)
+            .await;
+        if result.is_err() {
+            self.sent.lock().unwrap().remove(&message_id);
+        }
+        result
+    }
+}
+
+/// A client which sends encrypted messages in a session.
+///
+/// A background task receives messages and dispatches results to pending requests,
+/// so several requests can be invoked concurrently over one connection.
+pub struct EncryptedClient {
+    /// Shared state
+    inner: Arc<Inner>,
+    /// The background reader task
+    reader: JoinHandle<()>,
+    /// The background task which sends acknowledgments
+    acker: JoinHandle<()>,
+    /// The background task which sends pings
+    keepalive: JoinHandle<()>,
+}
+
+impl EncryptedClient {
+    /// Initialize the transport and start the background tasks.
+    /// Must be called within a tokio runtime.
+    /// * `client` - The connection
+    /// * `session` - The session
+    pub async fn new(client: Client, session: Session) -> Result<Self, ClientError> {
+        Self::start(
+            client,
+            Arc::new(session),
+            PendingMap::default(),
+            SentMap::default(),
+            false,
+        )
+        .await
+    }
+
+    /// Start a supervised connection which keeps pending requests when it is closed.
+    pub(crate) async fn supervised(client: Client, session: Session) -> Result<Self, ClientError> {
+        Self::start(
+            client,
+            Arc::new(session),
+            PendingMap::default(),
+            SentMap::default(),
+            true,
+        )
+        .await
+    }
+
+    /// Continue the session of a closed supervised connection over a new one.
+    /// Pending requests are kept and unacknowledged messages are resent.
+    /// Pending acknowledgments are taken over once the previous connection stops flushing them.
+    pub(crate) async fn resume(client: Client, previous: &Self) -> Result<Self, ClientError> {
+        let previous = &previous.inner;
+        // Message ids must keep increasing in the session.
+        client
+            .msg_id_generator()
+            .continue_from(previous.client.msg_id_generator());
+        let this = Self::start(
+            client,
+            previous.session.clone(),
+            previous.pending.clone(),
+            previous.sent.clone(),
+            true,
+        )
+        .await?;
+        this.inner.acks.push(&previous.hand_over_acks().await);
+        this.inner.resend_all().await;
+        Ok(this)
+    }
+
+    /// Continue the requests of a closed supervised connection in another session,
+    /// e.g. with a new authorization key. Pending requests are kept and
+    /// unacknowledged requests are resent. Messages which are not content-related are dropped.
+    pub(crate) async fn resume_in(
+        client: Client,
+        previous: &Self,
+        session: Session,
+    ) -> Result<Self, ClientError> {
+        let previous = &previous.inner;
+        previous
+            .sent
+            .lock()
+            .unwrap()
+            .retain(|_, v| v.content_related);
+        let this = Self::start(
+            client,
+            Arc::new(session),
+            previous.pending.clone(),
+            previous.sent.clone(),
+            true,
+        )
+        .await?;
+        this.inner.resend_all().await;
+        Ok(this)
+    }
+
+    /// Fail all pending requests. Used when a supervised connection is not resumed.
+    /// * `error` - The transport error which pending requests fail with, if it is not
+    ///   the one which closed the connection
+    pub(crate) fn abandon(&self, error: Option<TransportError>) {
+        if error.is_some() {
+            *self.inner.transport_error.lock().unwrap() = error;
+        }
+        self.inner.client.set_state(ConnectionState::Closed);
+        self.inner.fail_pending();
+    }
+
+    /// Initialize the transport and start the background tasks.
+    async fn start(
+        client: Client,
+        session: Arc<Session>,
+        pending: PendingMap,
+        sent: SentMap,
+        supervised: bool,
+    ) -> Result<Self, ClientError> {
+        client.init().await?;
+        let inner = Arc::new(Inner {
+            acks: AckManager::new(client.ack_batch_size()),
+            acks_handed_over: tokio::sync::Mutex::new(false),
+            client,
+            session,
+            pending,
+            sent,
+            rtt: Mutex::new(None),
+            closed: AtomicBool::new(false),
+            closed_notify: Notify::new(),
+            transport_error: Mutex::new(None),
+            supervised,
+            tasks: Mutex::new(Vec::new()),
+        });
+        let reader = tokio::spawn({
+            let inner = inner.clone();
+            async move { inner.read_loop().await }
+        });
+        let acker = tokio::spawn({
+            let inner = inner.clone();
+            async move { inner.ack_loop().await }
+        });
+        let keepalive = tokio::spawn({
+            let inner = inner.clone();
+            async move { inner.keepalive_loop().await }
+        });
+        *inner.tasks.lock().unwrap() = vec![
+            reader.abort_handle(),
+            acker.abort_handle(),
+            keepalive.abort_handle(),
+        ];
+        Ok(Self {
+            inner,
+            reader,
+            acker,
+            keepalive,
+        })
+    }
+
+    /// Returns the connection.
+    pub fn client(&self) -> &Client {
+        &self.inner.client
+    }
+
+    /// Returns the session.
+    pub fn session(&self) -> &Session {
+        &self.inner.session
+    }
+
+    /// Returns the round-trip time measured by the last keepalive ping.
+    pub fn rtt(&self) -> Option<Duration> {
+        *self.inner.rtt.lock().unwrap()
+    }
+
+    /// Returns true if the connection is considered dead.
+    pub fn is_closed(&self) -> bool {
+        self.inner.closed.load(Ordering::SeqCst)
+    }
+
+    /// Returns the transport error which closed the connection.
+    /// See [TransportError::recovery] for what should be done before connecting again.
+    pub fn transport_error(&self) -> Option<TransportError> {
+        *self.inner.transport_error.lock().unwrap()
+    }
+
+    /// Wait until the connection is considered dead,
+    /// either because it is broken or because `pong` stops arriving.
+    /// A new connection should be established then.
+    pub async fn closed(&self) {
+        let notified = self.inner.closed_notify.notified();
+        if self.is_closed() {
+            return;
+        }
+        notified.await
+    }
+
+    /// Invoke a function and wait for its result.
+    /// * `function` - The function
+    pub async fn invoke<F: Function>(&self, function: &F) -> Result<F::Return, ClientError> {
+        self.inner.invoke(function).await
+    }
+
+    /// Invoke a function with a quick ACK requested.
+    /// Returns a future which resolves when server receives the request
+    /// and a future of the result. The quick ACK is not requested again if the request is resent,
+    /// so it resolves to [ClientError::Disconnected] if a supervised connection breaks
+    /// while the request is sent. [ClientError::UnsupportedTransport] is returned
+    /// if the transport does not support quick ACK.
+    /// * `function` - The function
+    pub async fn invoke_with_quick_ack<F: Function>(
+        &self,
+        function: &F,
+    ) -> Result<
+        (
+            QuickAck,
+            impl Future<Output = Result<F::Return, ClientError>>,
+        ),
+        ClientError,
+    > {
+        let (quick_ack, receiver) = self.inner.send_request(function, true).await?;
+        let result = async move {
+            let result = receiver.await.map_err(|_| ClientError::Disconnected)??;
+            Ok(F::Return::deserialize_from_bytes(&result)?)
+        };
+        Ok((quick_ack.unwrap_or_else(QuickAck::disconnected), result))
+    }
+}
+
+impl Drop for EncryptedClient {
+    fn drop(&mut self) {
+        self.reader.abort();
+        self.acker.abort();
+        self.keepalive.abort();
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_invoke() {
+    use super::test_server::{connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+    use crate::objects::mtproto_api::types::ResPQ;
+
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for _ in 0..2 {
+            requests.push(server.read().await);
+        }
+        // Answer in reverse order.
+        for request in requests.iter().rev() {
+            server.answer(request).await;
+        }
+    });
+    let client = EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    let (a, b) = (req_pq_multi::new(), req_pq_multi::new());
+    let (ra, rb) = tokio::join!(client.invoke(&a), client.invoke(&b));
+    let ResPQ::ResPQ(ra) = ra.unwrap();
+    let ResPQ::ResPQ(rb) = rb.unwrap();
+    assert_eq!(ra.nonce, a.nonce);
+    assert_eq!(rb.nonce, b.nonce);
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_concurrent_invoke_order() {
+    use super::test_server::{connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    const COUNT: usize = 16;
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        let mut requests = Vec::new();
+        for _ in 0..COUNT {
+            requests.push(server.read().await);
+        }
+        // Messages arrive in the order of their ids and sequence numbers.
+        for (i, pair) in requests.windows(2).enumerate() {
+            assert!(pair[0].message_id < pair[1].message_id, "{}", i);
+            assert_eq!(pair[0].seq_no + 2, pair[1].seq_no, "{}", i);
+        }
+        for request in requests.iter() {
+            server.answer(request).await;
+        }
+    });
+    let client = Arc::new(
+        EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+            .await
+            .unwrap(),
+    );
+    let tasks = (0..COUNT)
+        .map(|_| {
+            let client = client.clone();
+            tokio::spawn(async move { client.invoke(&req_pq_multi::new()).await })
+        })
+        .collect::<Vec<_>>();
+    for task in tasks {
+        task.await.unwrap().unwrap();
+    }
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_quick_ack_disconnected() {
+    use super::test_server::{connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let (client, server) = connect(test_builder());
+    let client = EncryptedClient::supervised(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    drop(server);
+    // The request is kept to be resent when the connection is resumed.
+    let (quick_ack, result) = client
+        .invoke_with_quick_ack(&req_pq_multi::new())
+        .await
+        .unwrap();
+    assert!(matches!(quick_ack.await, Err(ClientError::Disconnected)));
+    assert_eq!(client.inner.pending.lock().unwrap().len(), 1);
+    client.abandon(None);
+    assert!(matches!(result.await, Err(ClientError::Disconnected)));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_bad_server_salt() {
+    use super::test_server::{connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::constructors::bad_server_salt;
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+    use crate::objects::mtproto_api::types::ResPQ;
+
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        let request = server.read().await;
+        assert_eq!(request.salt, 1);
+        let notification = Box::new(bad_server_salt {
+            bad_msg_id: request.message_id,
+            bad_msg_seqno: request.seq_no,
+            error_code: 48,
+            new_server_salt: 2,
+        });
+        server
+            .reply(&request, notification.serialize_to_bytes())
+            .await;
+        let resent = server.read().await;
+        assert_eq!(resent.salt, 2);
+        assert!(resent.message_id > request.message_id);
+        assert_eq!(resent.payload, request.payload);
+        server.answer(&resent).await;
+    });
+    let client = EncryptedClient::new(client, Session::new(test_auth_key(), 1))
+        .await
+        .unwrap();
+    let request = req_pq_multi::new();
+    let ResPQ::ResPQ(result) = client.invoke(&request).await.unwrap();
+    assert_eq!(result.nonce, request.nonce);
+    assert_eq!(client.session().server_salt(), 2);
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_bad_msg_id() {
+    use super::test_server::{bad_msg, connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+    use crate::objects::mtproto_api::types::ResPQ;
+
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        let request = server.read().await;
+        // Server time is an hour ahead.
+        let server_id = request.message_id + (3600 << 32) + 1;
+        server.write(&bad_msg(&request, server_id, 16)).await;
+        let resent = server.read().await;
+        assert!(((resent.message_id >> 32) - (server_id >> 32)).abs() <= 1);
+        assert_eq!(resent.seq_no, 3);
+        assert_eq!(resent.payload, request.payload);
+        server.answer(&resent).await;
+    });
+    let client = EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    let request = req_pq_multi::new();
+    let ResPQ::ResPQ(result) = client.invoke(&request).await.unwrap();
+    assert_eq!(result.nonce, request.nonce);
+    assert!((client.client().msg_id_generator().time_offset() - 3600).abs() <= 1);
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_msg_id_too_high() {
+    use super::test_server::{bad_msg, connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        let request = server.read().await;
+        // Server time is an hour behind.
+        let server_id = request.message_id - (3600 << 32) + 1;
+        server.write(&bad_msg(&request, server_id, 17)).await;
+        // The request is resent in a new session under an id which follows server time.
+        let resent = server.read().await;
+        assert_ne!(resent.session_id, request.session_id);
+        assert_eq!(resent.seq_no, 1);
+        assert!(((resent.message_id >> 32) - (server_id >> 32)).abs() <= 1);
+        assert_eq!(resent.payload, request.payload);
+        server.answer(&resent).await;
+        resent.session_id
+    });
+    let client = EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    client.invoke(&req_pq_multi::new()).await.unwrap();
+    assert!((client.client().msg_id_generator().time_offset() + 3600).abs() <= 1);
+    assert_eq!(client.session().session_id(), server.await.unwrap());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_bad_seq_no() {
+    use super::test_server::{bad_msg, connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        let request = server.read().await;
+        server
+            .write(&bad_msg(&request, request.message_id + 1, 32))
+            .await;
+        // The request is resent in a new session.
+        let resent = server.read().await;
+        assert_ne!(resent.session_id, request.session_id);
+        assert_eq!(resent.seq_no, 1);
+        assert!(resent.message_id > request.message_id);
+        assert_eq!(resent.payload, request.payload);
+        server.answer(&resent).await;
+        resent.session_id
+    });
+    let client = EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    client.invoke(&req_pq_multi::new()).await.unwrap();
+    assert_eq!(client.session().session_id(), server.await.unwrap());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_seq_no_parity() {
+    use super::test_server::{bad_msg, connect, test_auth_key, test_builder};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        let mut request = server.read().await;
+        for (code, parity) in [(34, 0), (35, 1)] {
+            server
+                .write(&bad_msg(&request, request.message_id + 1, code))
+                .await;
+            let resent = server.read().await;
+            assert_eq!(resent.seq_no % 2, parity);
+            assert!(resent.message_id > request.message_id);
+            assert_eq!(resent.payload, request.payload);
+            request = resent;
+        }
+        server.answer(&request).await;
+    });
+    let client = EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    client.invoke(&req_pq_multi::new()).await.unwrap();
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_rejected_container() {
+    use super::test_server::{bad_msg, connect, test_auth_key, test_builder, unsolicited};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let (client, mut server) = connect(test_builder().ack_delay(Duration::from_millis(500)));
+    let session = Session::new(test_auth_key(), 0);
+    let session_id = session.session_id();
+    let server = tokio::spawn(async move {
+        server.write(&unsolicited(session_id, 1 << 32)).await;
+        let container = server.read().await;
+        assert_eq!(message::from(&container).unpack().unwrap().len(), 2);
+        server
+            .write(&bad_msg(&container, container.message_id + 1, 64))
+            .await;
+        // The request is resent at once and the acknowledgment with the next batch.
+        let resent = server.read().await;
+        assert_eq!(resent.seq_no % 2, 1);
+        assert!(resent.message_id > container.message_id);
+        let ack = server.read().await;
+        let ack = msgs_ack::deserialize_from_bytes(&ack.payload[4..]).unwrap();
+        assert_eq!(*ack.msg_ids, vec![1 << 32]);
+        server.answer(&resent).await;
+        server
+    });
+    let client = EncryptedClient::new(client, session).await.unwrap();
+    // Wait for the reader to receive the message.
+    client.inner.acks.arrived().await;
+    client.invoke(&req_pq_multi::new()).await.unwrap();
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_ack_delay() {
+    use super::test_server::{connect, test_auth_key, test_builder, unsolicited};
+
+    tokio::time::pause();
+    let (client, mut server) = connect(
+        test_builder()
+            .ack_batch_size(2)
+            .ack_delay(Duration::from_millis(200)),
+    );
+    let session = Session::new(test_auth_key(), 0);
+    let session_id = session.session_id();
+    let server = tokio::spawn(async move {
+        for ids in [vec![1 << 32, 2 << 32], vec![3 << 32]] {
+            let start = tokio::time::Instant::now();
+            for message_id in ids.iter() {
+                server.write(&unsolicited(session_id, *message_id)).await;
+            }
+            let ack = server.read().await;
+            let ack = msgs_ack::deserialize_from_bytes(&ack.payload[4..]).unwrap();
+            assert_eq!(*ack.msg_ids, ids);
+            // A full batch is sent at once, the rest after the delay.
+            if ids.len() == 2 {
+                assert!(start.elapsed() < Duration::from_millis(200));
+            } else {
+                assert!(start.elapsed() >= Duration::from_millis(200));
+            }
+        }
+        server
+    });
+    let _client = EncryptedClient::new(client, session).await.unwrap();
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_ack_in_container() {
+    use super::test_server::{connect, test_auth_key, test_builder, unsolicited};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let (client, mut server) = connect(test_builder().ack_delay(Duration::from_secs(60)));
+    let session = Session::new(test_auth_key(), 0);
+    let session_id = session.session_id();
+    let server = tokio::spawn(async move {
+        server.write(&unsolicited(session_id, 1 << 32)).await;
+        let container = server.read().await;
+        assert_eq!(container.seq_no % 2, 0);
+        let messages = message::from(&container).unpack().unwrap();
+        assert_eq!(messages.len(), 2);
+        let ack = msgs_ack::deserialize_from_bytes(&messages[0].body[4..]).unwrap();
+        assert_eq!(*ack.msg_ids, vec![1 << 32]);
+        assert!(!messages[0].is_content_related());
+        assert!(messages[1].is_content_related());
+        assert!(messages[0].msg_id < messages[1].msg_id);
+        assert!(messages[1].msg_id < container.message_id);
+        let request = DecryptedMessage {
+            salt: container.salt,
+            session_id,
+            message_id: messages[1].msg_id,
+            seq_no: messages[1].seqno,
+            payload: messages[1].body.clone(),
+        };
+        server.answer(&request).await;
+        server
+    });
+    let client = EncryptedClient::new(client, session).await.unwrap();
+    // Wait for the reader to receive the message.
+    client.inner.acks.arrived().await;
+    client.invoke(&req_pq_multi::new()).await.unwrap();
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_resume_acks() {
+    use super::test_server::{connect, test_auth_key, test_builder, unsolicited};
+
+    tokio::time::pause();
+    let builder = test_builder().ack_delay(Duration::from_millis(100));
+    let (client, mut server) = connect(builder.clone());
+    let session = Session::new(test_auth_key(), 0);
+    let session_id = session.session_id();
+    let previous = EncryptedClient::supervised(client, session).await.unwrap();
+    server.write(&unsolicited(session_id, 1 << 32)).await;
+    previous.inner.acks.arrived().await;
+    drop(server);
+    previous.closed().await;
+    let (client, mut server) = connect(builder);
+    let _client = EncryptedClient::resume(client, &previous).await.unwrap();
+    // The acknowledgment is sent once over the new connection.
+    let ack = server.read().await;
+    let ack = msgs_ack::deserialize_from_bytes(&ack.payload[4..]).unwrap();
+    assert_eq!(*ack.msg_ids, vec![1 << 32]);
+    assert!(previous.inner.acks.take().is_empty());
+    assert!(*previous.inner.acks_handed_over.lock().await);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_keepalive() {
+    use super::test_server::{connect, test_auth_key, test_builder};
+
+    tokio::time::pause();
+    let (client, mut server) = connect(
+        test_builder()
+            .ping_interval(Duration::from_millis(50))
+            .disconnect_delay(Duration::from_secs(1)),
+    );
+    let server = tokio::spawn(async move {
+        let request = server.read().await;
+        assert_eq!(
+            &request.payload[..4],
+            &ping_delay_disconnect::type_id2().to_le_bytes()
+        );
+        let ping_id = i64::deserialize_from_bytes(&request.payload[4..12]).unwrap();
+        assert_eq!(
+            i32::deserialize_from_bytes(&request.payload[12..]).unwrap(),
+            1
+        );
+        let message = DecryptedMessage {
+            salt: request.salt,
+            session_id: request.session_id,
+            message_id: request.message_id + 1,
+            seq_no: 0,
+            payload: Box::new(pong {
+                msg_id: request.message_id,
+                ping_id,
+            })
+            .serialize_to_bytes(),
+        };
+        server.write(&message).await;
+        // Stop answering pings.
+        server.read().await;
+        server
+    });
+    let mut client = EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    tokio::time::timeout(Duration::from_secs(5), client.closed())
+        .await
+        .unwrap();
+    assert!(client.rtt().is_some());
+    assert!(client.is_closed());
+    // The timed out ping is not waited for any more.
+    assert!(client.inner.pending.lock().unwrap().is_empty());
+    assert!(!client
+        .inner
+        .sent
+        .lock()
+        .unwrap()
+        .values()
+        .any(|v| v.content_related));
+    // The background tasks stop.
+    for task in [&mut client.reader, &mut client.acker, &mut client.keepalive] {
+        tokio::time::timeout(Duration::from_secs(5), task)
+            .await
+            .unwrap()
+            .ok();
+    }
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_transport_error() {
+    use super::test_server::{connect, test_auth_key, test_builder};
+    use super::Recovery;
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let (client, mut server) = connect(test_builder());
+    let server = tokio::spawn(async move {
+        server.read().await;
+        server.write_transport_error(-404).await;
+        server
+    });
+    let client = EncryptedClient::new(client, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.invoke(&req_pq_multi::new()).await,
+        Err(ClientError::TransportError(TransportError::AuthKeyNotFound))
+    ));
+    assert!(client.is_closed());
+    let error = client.transport_error().unwrap();
+    assert_eq!(error.code(), -404);
+    assert_eq!(error.recovery(), Recovery::RegenerateAuthKey);
+    server.await.unwrap();
+}
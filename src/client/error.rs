@@ -7,8 +7,170 @@ pub enum ClientError {
     DeserializeError(crate::objects::DeserializeError),
     /// Network error
     TokioError(tokio::io::Error),
-    /// Server error
-    ServerError(i32),
+    /// Server returned a transport error
+    TransportError(TransportError),
     /// Crc32 check failed.
     Crc32CheckFailed,
+    /// The sequence number of the received packet is not the expected one.
+    /// Packets are skipped, reordered or duplicated.
+    #[display(fmt = "SeqNoMismatched(expected {}, received {})", expected, received)]
+    SeqNoMismatched {
+        /// The expected sequence number
+        expected: u32,
+        /// The received sequence number
+        received: u32,
+    },
+    /// The length of the received frame is not valid or too long.
+    BadFrameLength(usize),
+    /// Failed to encrypt message
+    EncryptError(crate::aes::EncryptError),
+    /// Failed to decrypt message
+    MessageDecryptError(crate::aes::MessageDecryptError),
+    /// `auth_key_id` of the received message is not the one of the authorization key.
+    AuthKeyIdMismatched,
+    /// The length of padding in the received message is not valid.
+    BadPadding,
+    /// `session_id` of the received message is not the one of the session.
+    SessionIdMismatched,
+    /// Server returned `rpc_error`
+    RpcError(crate::objects::mtproto_api::constructors::rpc_error),
+    /// The connection is closed, e.g. by server, before the result is received.
+    Disconnected,
+    /// The request is ignored by server too many times.
+    BadMessage(crate::objects::mtproto_api::constructors::BadMsgCode),
+    /// The transport type can not be used with the options.
+    UnsupportedTransport,
+    /// OpenSSL Error
+    OpenSSLError(openssl::error::ErrorStack),
+    /// The MTProxy secret is not valid.
+    InvalidProxySecret,
+    /// The proxy refused the connection or its response is not valid.
+    ProxyHandshakeFailed,
+    /// The HTTP response is not valid.
+    BadHttpResponse,
+    /// The WebSocket URL is not valid.
+    InvalidWebSocketUrl,
+    /// The server refused to upgrade the connection to WebSocket.
+    WebSocketHandshakeFailed,
+    /// The WebSocket frame is not valid.
+    BadWebSocketFrame,
+    /// The connection is not established within [ClientBuilder::connect_timeout](super::ClientBuilder::connect_timeout).
+    ConnectTimeout,
+    /// The rest of a frame is not received within [ClientBuilder::read_timeout](super::ClientBuilder::read_timeout).
+    ReadTimeout,
+    /// No frame is received within [ClientBuilder::idle_timeout](super::ClientBuilder::idle_timeout).
+    IdleTimeout,
+}
+
+impl ClientError {
+    /// Returns true if the connection can not be used after the error.
+    pub fn is_connection_error(&self) -> bool {
+        matches!(
+            self,
+            Self::TokioError(_)
+                | Self::TransportError(_)
+                | Self::Crc32CheckFailed
+                | Self::SeqNoMismatched { .. }
+                | Self::BadFrameLength(_)
+                | Self::Disconnected
+                | Self::BadWebSocketFrame
+                | Self::ConnectTimeout
+                | Self::ReadTimeout
+                | Self::IdleTimeout
+        )
+    }
+}
+
+/// Transport error codes which are sent by server instead of a packet.
+/// [More](https://core.telegram.org/mtproto/mtproto-transports#transport-errors)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum TransportError {
+    /// `-404`: The authorization key is not found or the message can not be decrypted.
+    AuthKeyNotFound,
+    /// `-429`: Too many connections are established to the same IP or too many requests are sent.
+    Flood,
+    /// `-444`: The DC id is not valid. Only sent while connecting through MTProxy.
+    InvalidDc,
+    /// Unknown error code
+    #[display(fmt = "Unknown({})", _0)]
+    Unknown(i32),
+}
+
+impl TransportError {
+    /// Returns the error code.
+    pub fn code(&self) -> i32 {
+        match self {
+            Self::AuthKeyNotFound => -404,
+            Self::Flood => -429,
+            Self::InvalidDc => -444,
+            Self::Unknown(v) => *v,
+        }
+    }
+
+    /// Returns what should be done before connecting again.
+    pub fn recovery(&self) -> Recovery {
+        match self {
+            Self::AuthKeyNotFound => Recovery::RegenerateAuthKey,
+            Self::Flood => Recovery::Backoff,
+            Self::InvalidDc => Recovery::SwitchDc,
+            Self::Unknown(_) => Recovery::Reconnect,
+        }
+    }
+}
+
+impl From<i32> for TransportError {
+    fn from(code: i32) -> Self {
+        match code {
+            -404 => Self::AuthKeyNotFound,
+            -429 => Self::Flood,
+            -444 => Self::InvalidDc,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+/// The way to recover from a [TransportError]
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Recovery {
+    /// Drop the authorization key and generate a new one.
+    RegenerateAuthKey,
+    /// Wait for a while before connecting again.
+    Backoff,
+    /// Connect to another DC.
+    SwitchDc,
+    /// Connect again.
+    Reconnect,
+}
+
+#[derive(Debug, derive_more::From)]
+/// Error when generating authorization key
+pub enum AuthKeyError {
+    /// Client error
+    ClientError(ClientError),
+    /// Failed to deserialize response
+    DeserializeError(crate::objects::DeserializeError),
+    /// OpenSSL Error
+    OpenSSLError(openssl::error::ErrorStack),
+    /// Failed to factorize `pq`
+    FactorizeError(crate::objects::mtproto_api::constructors::FactorizeError),
+    /// Failed to encrypt data
+    EncryptError(crate::aes::EncryptError),
+    /// Failed to decrypt server's answer
+    DecryptError(crate::objects::mtproto_api::constructors::DecryptError),
+    /// `dh_prime` or `g_a` is not valid.
+    CheckDhPrimeError(crate::objects::mtproto_api::constructors::CheckDhPrimeError),
+    /// None of the keys is accepted by server.
+    NoSuitableKey,
+    /// `nonce` or `server_nonce` mismatched
+    NonceMismatched,
+    /// Server returned `server_DH_params_fail`
+    ServerDhParamsFailed,
+    /// `new_nonce_hash` in server's answer mismatched
+    NewNonceHashMismatched,
+    /// Server returned `dh_gen_retry` too many times
+    DhGenRetry,
+    /// Server returned `dh_gen_fail`
+    DhGenFailed,
+    /// The generated key is not valid.
+    BadAuthKey,
 }
@@ -0,0 +1,124 @@
+use super::auth_key::{AuthKey, GeneratedAuthKey};
+use super::client::Client;
+use super::error::AuthKeyError;
+use super::state::ConnectionState;
+use crate::objects::mtproto_api::functions::{req_DH_params, req_pq_multi, set_client_DH_params};
+use crate::objects::mtproto_api::types::{
+    Client_DH_Inner_Data, P_Q_inner_data, RSAPublicKey, ResPQ, Server_DH_Inner_Data,
+    Server_DH_Params, Set_client_DH_params_answer,
+};
+use crate::objects::traits::Serialize;
+use openssl::{pkey::Public, rsa::Rsa};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The maximum number of `dh_gen_retry` accepted before giving up.
+const MAX_DH_GEN_RETRY: usize = 5;
+
+impl Client {
+    /// Create an authorization key. [More](https://core.telegram.org/mtproto/auth_key)
+    /// * `keys` - Known server public keys. The one whose fingerprint is returned by server will be used.
+    /// * `dc` - The ID of the DC which the key is generated for.
+    /// * `temp` - Set `expires_in` (in seconds) to create a temporary authorization key.
+    pub async fn generate_auth_key(
+        &self,
+        keys: &[RSAPublicKey],
+        dc: i32,
+        temp: Option<i32>,
+    ) -> Result<GeneratedAuthKey, AuthKeyError> {
+        self.init().await?;
+        self.set_state(ConnectionState::Handshaking);
+        let result = self.exchange_auth_key(keys, dc, temp).await;
+        // A connection error has already closed the connection.
+        self.set_state(ConnectionState::Ready);
+        result
+    }
+
+    /// Do the DH exchange of [Client::generate_auth_key].
+    async fn exchange_auth_key(
+        &self,
+        keys: &[RSAPublicKey],
+        dc: i32,
+        temp: Option<i32>,
+    ) -> Result<GeneratedAuthKey, AuthKeyError> {
+        let req = req_pq_multi::new();
+        let ResPQ::ResPQ(res_pq) = self.invoke_unencrypted(&req).await?;
+        if res_pq.nonce != req.nonce {
+            return Err(AuthKeyError::NonceMismatched);
+        }
+        let (fingerprint, key) = keys
+            .iter()
+            .map(|k| (k.sha1_as_i64(), k))
+            .find(|(f, _)| res_pq.server_public_key_fingerprints.contains(f))
+            .ok_or(AuthKeyError::NoSuitableKey)?;
+        let key: Rsa<Public> = key.try_into()?;
+        let mut p_q_inner_data = P_Q_inner_data::new(&res_pq, temp)?;
+        let new_nonce = match &mut p_q_inner_data {
+            P_Q_inner_data::P_Q_inner_data_dc(v) => {
+                v.dc = dc;
+                v.new_nonce.clone()
+            }
+            P_Q_inner_data::P_Q_inner_data_temp_dc(v) => {
+                v.dc = dc;
+                v.new_nonce.clone()
+            }
+        };
+        let req = req_DH_params::new(&p_q_inner_data, fingerprint, &key)?;
+        let params = match self.invoke_unencrypted(&req).await? {
+            Server_DH_Params::Ok(v) => v,
+            Server_DH_Params::Failed(_) => return Err(AuthKeyError::ServerDhParamsFailed),
+        };
+        let server_inner_data = params.decrypt_answer(&p_q_inner_data)?;
+        let Server_DH_Inner_Data::Boxed(server_inner) = &server_inner_data;
+        if server_inner.nonce != res_pq.nonce || server_inner.server_nonce != res_pq.server_nonce {
+            return Err(AuthKeyError::NonceMismatched);
+        }
+        server_inner.check_dh_prime()?;
+        server_inner.check_g_a()?;
+        let now = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .unwrap()
+            .as_secs() as i64;
+        let time_offset = (server_inner.server_time as i64 - now) as i32;
+        self.msg_id_generator().set_time_offset(time_offset);
+        let mut retry_id = 0;
+        let mut retry = 0;
+        let auth_key = loop {
+            let client_inner_data = Client_DH_Inner_Data::new(&server_inner_data, retry_id)?;
+            client_inner_data.check_g_b(server_inner)?;
+            let req = set_client_DH_params::new(&client_inner_data, &p_q_inner_data)?;
+            let answer = self.invoke_unencrypted(&req).await?;
+            if answer.nonces() != (res_pq.nonce, res_pq.server_nonce) {
+                return Err(AuthKeyError::NonceMismatched);
+            }
+            let auth_key = AuthKey::from_slice(&client_inner_data.auth_key(server_inner)?)
+                .ok_or(AuthKeyError::BadAuthKey)?;
+            if !answer.check_new_nonce_hash(&new_nonce, auth_key.aux_hash()) {
+                return Err(AuthKeyError::NewNonceHashMismatched);
+            }
+            match answer {
+                Set_client_DH_params_answer::Dh_gen_ok(_) => break auth_key,
+                Set_client_DH_params_answer::Dh_gen_retry(_) => {
+                    retry += 1;
+                    if retry > MAX_DH_GEN_RETRY {
+                        return Err(AuthKeyError::DhGenRetry);
+                    }
+                    retry_id = auth_key.aux_hash();
+                }
+                Set_client_DH_params_answer::Dh_gen_fail(_) => {
+                    return Err(AuthKeyError::DhGenFailed)
+                }
+            }
+        };
+        let new_nonce = new_nonce.serialize_to_bytes();
+        let server_nonce = res_pq.server_nonce.serialize_to_bytes();
+        let mut salt = [0u8; 8];
+        for (i, v) in salt.iter_mut().enumerate() {
+            *v = new_nonce[i] ^ server_nonce[i];
+        }
+        Ok(GeneratedAuthKey {
+            auth_key,
+            server_salt: i64::from_le_bytes(salt),
+            time_offset,
+        })
+    }
+}
@@ -0,0 +1,130 @@
+use std::sync::atomic::{AtomicI64, Ordering};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Returns current unix time in nanoseconds.
+fn now_nanos() -> i64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_nanos() as i64
+}
+
+/// Message identifier generator. [More](https://core.telegram.org/mtproto/description#message-identifier-msg-id)
+///
+/// The generated ids are strictly monotonic and divisible by 4.
+/// The upper 32 bits are unix time in seconds and the lower 32 bits are the fractional part of second.
+#[derive(Debug, Default)]
+pub struct MessageIdGenerator {
+    /// The last generated message id
+    last: AtomicI64,
+    /// The difference between server time and local time in nanoseconds.
+    time_offset: AtomicI64,
+}
+
+impl MessageIdGenerator {
+    /// Create a new generator.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Returns the difference between server time and local time in seconds.
+    pub fn time_offset(&self) -> i32 {
+        (self.time_offset.load(Ordering::SeqCst) / 1_000_000_000) as i32
+    }
+
+    /// Set the difference between server time and local time.
+    /// * `time_offset` - Server time minus local time in seconds.
+    pub fn set_time_offset(&self, time_offset: i32) {
+        self.time_offset
+            .store(time_offset as i64 * 1_000_000_000, Ordering::SeqCst);
+    }
+
+    /// Returns the estimated server time in seconds.
+    pub fn server_time(&self) -> i64 {
+        (now_nanos() + self.time_offset.load(Ordering::SeqCst)) / 1_000_000_000
+    }
+
+    /// Correct the time offset from a message id generated by server.
+    /// Used when server complains that message id is too low or too high
+    /// (See [bad_msg_notification](https://core.telegram.org/mtproto/service_messages_about_messages#notice-of-ignored-error-message)).
+    /// The generated ids stay monotonic, so they follow a clock moved back behind the last id
+    /// only after [MessageIdGenerator::reset].
+    /// Returns true if the corrected clock is behind the last id.
+    /// * `msg_id` - The message id of a message sent by server
+    pub fn correct_time(&self, msg_id: i64) -> bool {
+        let secs = msg_id >> 32;
+        let nanos = ((msg_id & 0xffffffff) * 1_000_000_000) >> 32;
+        let server_time = secs * 1_000_000_000 + nanos;
+        self.time_offset
+            .store(server_time - now_nanos(), Ordering::SeqCst);
+        msg_id <= self.last.load(Ordering::SeqCst)
+    }
+
+    /// Forget the last id, so that the generated ids follow the clock even if it is moved back.
+    /// Server only requires the ids to increase within a session, so it must be used
+    /// only when a new session is started.
+    pub fn reset(&self) {
+        self.last.store(0, Ordering::SeqCst);
+    }
+
+    /// Continue from another generator, e.g. the one of a previous connection in the same session.
+    /// The time offset is copied and the generated ids stay greater than the ones of `previous`.
+    pub fn continue_from(&self, previous: &MessageIdGenerator) {
+        self.time_offset.store(
+            previous.time_offset.load(Ordering::SeqCst),
+            Ordering::SeqCst,
+        );
+        self.last
+            .fetch_max(previous.last.load(Ordering::SeqCst), Ordering::SeqCst);
+    }
+
+    /// Generate a new message id.
+    pub fn next(&self) -> i64 {
+        let now = now_nanos() + self.time_offset.load(Ordering::SeqCst);
+        let secs = now / 1_000_000_000;
+        let fraction = ((now % 1_000_000_000) << 32) / 1_000_000_000;
+        let id = ((secs << 32) | fraction) & !3;
+        let mut last = self.last.load(Ordering::SeqCst);
+        loop {
+            let next = if id > last { id } else { last + 4 };
+            match self
+                .last
+                .compare_exchange(last, next, Ordering::SeqCst, Ordering::SeqCst)
+            {
+                Ok(_) => return next,
+                Err(v) => last = v,
+            }
+        }
+    }
+}
+
+#[test]
+fn test_message_id() {
+    let generator = MessageIdGenerator::new();
+    let mut last = 0;
+    for _ in 0..1000 {
+        let id = generator.next();
+        assert_eq!(id % 4, 0);
+        assert!(id > last);
+        last = id;
+    }
+    let now = now_nanos() / 1_000_000_000;
+    assert!(((last >> 32) - now).abs() <= 1);
+    generator.set_time_offset(3600);
+    assert_eq!(generator.time_offset(), 3600);
+    assert!(((generator.next() >> 32) - now - 3600).abs() <= 1);
+    let last = generator.next();
+    assert!(generator.correct_time((now - 7200) << 32));
+    assert!((generator.time_offset() + 7200).abs() <= 1);
+    generator.reset();
+    let id = generator.next();
+    assert!(id < last);
+    assert!(((id >> 32) - now + 7200).abs() <= 1);
+    assert!(!generator.correct_time((now + 7200) << 32));
+    assert!(((generator.next() >> 32) - now - 7200).abs() <= 1);
+    let last = generator.next();
+    let next = MessageIdGenerator::new();
+    next.continue_from(&generator);
+    assert_eq!(next.time_offset(), generator.time_offset());
+    assert!(next.next() > last);
+}
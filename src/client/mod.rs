@@ -1,9 +1,55 @@
+/// Acknowledgment of received messages
+mod ack;
+/// Authorization key
+mod auth_key;
 /// Low api level client
+#[allow(clippy::module_inception)]
 mod client;
+/// Client with encrypted messages
+mod encrypted;
 /// Client error
 mod error;
+/// Authorization key generation
+mod handshake;
+/// Message identifier generator
+mod message_id;
+/// Transport obfuscation
+mod obfuscation;
+/// MTProxy
+mod proxy;
+/// Quick acknowledgment of sent messages
+mod quick_ack;
+/// Automatic reconnection
+mod reconnect;
+/// MTProto session
+mod session;
+/// Connection state
+mod state;
+/// Fake server for tests
+#[cfg(test)]
+mod test_server;
+/// SOCKS5 and HTTP proxy
+mod tunnel;
+/// WebSocket transport
+mod websocket;
 
+pub use auth_key::AuthKey;
+pub use auth_key::GeneratedAuthKey;
 pub use client::Client;
 pub use client::ClientBuilder;
 pub use client::TransportType;
+pub use encrypted::EncryptedClient;
+pub use error::AuthKeyError;
 pub use error::ClientError;
+pub use error::Recovery;
+pub use error::TransportError;
+pub use message_id::MessageIdGenerator;
+pub use proxy::MtProxy;
+pub use proxy::MtProxySecret;
+pub use quick_ack::QuickAck;
+pub use reconnect::DcOption;
+pub use reconnect::ReconnectingClient;
+pub use session::Session;
+pub use state::ConnectionState;
+pub use tunnel::Proxy;
+pub use websocket::WebSocket;
@@ -0,0 +1,119 @@
+use super::TransportType;
+use openssl::error::ErrorStack;
+use openssl::sha::Sha256;
+use openssl::symm::{Cipher, Crypter, Mode};
+
+/// The first 4 bytes of the init payload which are not allowed,
+/// because they could be confused with another protocol.
+const FORBIDDEN_PREFIXES: [[u8; 4]; 7] = [
+    *b"HEAD",
+    *b"POST",
+    *b"GET ",
+    *b"OPTI",
+    [0x16, 0x03, 0x01, 0x02],
+    [0xdd, 0xdd, 0xdd, 0xdd],
+    [0xee, 0xee, 0xee, 0xee],
+];
+
+/// AES-256-CTR stream which is used in one direction of an obfuscated connection.
+pub struct AesCtr(Crypter);
+
+impl AesCtr {
+    /// Create a new stream.
+    pub fn new(key: &[u8], iv: &[u8]) -> Result<Self, ErrorStack> {
+        Ok(Self(Crypter::new(
+            Cipher::aes_256_ctr(),
+            Mode::Encrypt,
+            key,
+            Some(iv),
+        )?))
+    }
+
+    /// Encrypt or decrypt data in place.
+    pub fn apply(&mut self, data: &mut [u8]) -> Result<(), ErrorStack> {
+        let input = data.to_vec();
+        // CTR mode is a stream cipher so the output is as long as the input.
+        // One extra block is required by OpenSSL.
+        let mut output = vec![0u8; input.len() + 16];
+        let le = self.0.update(&input, &mut output)?;
+        data.copy_from_slice(&output[..le]);
+        Ok(())
+    }
+}
+
+/// Returns the protocol tag of the transport in an obfuscated connection.
+/// [TransportType::Full] and [TransportType::Http] can not be obfuscated.
+pub fn protocol_tag(ty: &TransportType) -> Option<[u8; 4]> {
+    match ty {
+        TransportType::Abridged => Some([0xef; 4]),
+        TransportType::Intermediate => Some([0xee; 4]),
+        TransportType::PaddedIntermediate => Some([0xdd; 4]),
+        TransportType::Full | TransportType::Http => None,
+    }
+}
+
+/// Generate the 64-byte init payload of an obfuscated connection.
+/// [More](https://core.telegram.org/mtproto/mtproto-transports#transport-obfuscation)
+///
+/// Returns `(payload, encryptor, decryptor)`. The payload should be sent as is
+/// and all the following data should be processed with the streams.
+/// * `tag` - The protocol tag
+/// * `dc_id` - The DC id. Only required when connecting through a proxy.
+/// * `secret` - The key of MTProxy secret. Keys are `SHA256(key + secret)` if it is set.
+pub fn init_payload(
+    tag: [u8; 4],
+    dc_id: i16,
+    secret: Option<&[u8; 16]>,
+) -> Result<([u8; 64], AesCtr, AesCtr), ErrorStack> {
+    let mut init = [0u8; 64];
+    loop {
+        openssl::rand::rand_bytes(&mut init)?;
+        if init[0] != 0xef
+            && !FORBIDDEN_PREFIXES.iter().any(|v| v[..] == init[0..4])
+            && init[4..8] != [0; 4]
+        {
+            break;
+        }
+    }
+    init[56..60].copy_from_slice(&tag);
+    init[60..62].copy_from_slice(&dc_id.to_le_bytes());
+    let reversed = init[8..56].iter().rev().copied().collect::<Vec<_>>();
+    let key = |key: &[u8]| match secret {
+        Some(secret) => {
+            let mut hasher = Sha256::new();
+            hasher.update(key);
+            hasher.update(secret);
+            hasher.finish().to_vec()
+        }
+        None => key.to_vec(),
+    };
+    let mut encryptor = AesCtr::new(&key(&init[8..40]), &init[40..56])?;
+    let decryptor = AesCtr::new(&key(&reversed[0..32]), &reversed[32..48])?;
+    let mut encrypted = init;
+    encryptor.apply(&mut encrypted)?;
+    init[56..64].copy_from_slice(&encrypted[56..64]);
+    Ok((init, encryptor, decryptor))
+}
+
+#[test]
+fn test_init_payload() {
+    let (init, mut encryptor, mut decryptor) = init_payload([0xdd; 4], 2, None).unwrap();
+    assert_ne!(init[0], 0xef);
+    assert!(!FORBIDDEN_PREFIXES.iter().any(|v| v[..] == init[0..4]));
+    assert_ne!(init[4..8], [0; 4]);
+    // What server does
+    let mut server_decryptor = AesCtr::new(&init[8..40], &init[40..56]).unwrap();
+    let mut decrypted = init;
+    server_decryptor.apply(&mut decrypted).unwrap();
+    assert_eq!(decrypted[56..60], [0xdd; 4]);
+    assert_eq!(decrypted[60..62], 2i16.to_le_bytes());
+    let reversed = init[8..56].iter().rev().copied().collect::<Vec<_>>();
+    let mut server_encryptor = AesCtr::new(&reversed[0..32], &reversed[32..48]).unwrap();
+    let mut data = *b"0123456789";
+    encryptor.apply(&mut data).unwrap();
+    server_decryptor.apply(&mut data).unwrap();
+    assert_eq!(&data, b"0123456789");
+    server_encryptor.apply(&mut data).unwrap();
+    decryptor.apply(&mut data).unwrap();
+    assert_eq!(&data, b"0123456789");
+}
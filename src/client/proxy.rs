@@ -0,0 +1,384 @@
+use super::{ClientError, TransportType};
+use base64::Engine;
+use openssl::error::ErrorStack;
+use openssl::hash::MessageDigest;
+use openssl::memcmp;
+use openssl::pkey::PKey;
+use openssl::sign::Signer;
+use std::str::FromStr;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// The length of fake TLS ClientHello
+const CLIENT_HELLO_LEN: usize = 517;
+
+/// The offset of the random field in fake TLS ClientHello and ServerHello
+const DIGEST_OFFSET: usize = 11;
+
+/// The maximum length of a domain name, which must fit in fake TLS ClientHello
+const MAX_DOMAIN_LEN: usize = 253;
+
+/// The maximum payload length of a TLS record
+pub const MAX_TLS_RECORD_LEN: usize = 16384;
+
+/// TLS ChangeCipherSpec record sent by client before the first application data record
+pub const CHANGE_CIPHER_SPEC: [u8; 6] = [0x14, 0x03, 0x03, 0x00, 0x01, 0x01];
+
+/// MTProxy secret. [More](https://core.telegram.org/mtproto/mtproto-transports#transport-obfuscation)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum MtProxySecret {
+    /// A plain 16-byte secret. Any transport except [TransportType::Full] can be used.
+    Plain([u8; 16]),
+    /// A secret starting with `dd`. Only [TransportType::PaddedIntermediate] can be used.
+    Padded([u8; 16]),
+    /// A secret starting with `ee` followed by a domain.
+    /// The connection looks like a TLS connection to the domain.
+    FakeTls([u8; 16], String),
+}
+
+impl MtProxySecret {
+    /// Parse a secret in hex or base64.
+    pub fn parse(secret: &str) -> Result<Self, ClientError> {
+        let data = decode_hex(secret)
+            .or_else(|| {
+                base64::engine::general_purpose::URL_SAFE_NO_PAD
+                    .decode(secret.trim_end_matches('='))
+                    .ok()
+            })
+            .or_else(|| {
+                base64::engine::general_purpose::STANDARD_NO_PAD
+                    .decode(secret.trim_end_matches('='))
+                    .ok()
+            })
+            .ok_or(ClientError::InvalidProxySecret)?;
+        let key = |v: &[u8]| -> Result<[u8; 16], ClientError> {
+            v.get(0..16)
+                .and_then(|v| v.try_into().ok())
+                .ok_or(ClientError::InvalidProxySecret)
+        };
+        match data.first() {
+            _ if data.len() == 16 => Ok(Self::Plain(key(&data)?)),
+            Some(0xdd) if data.len() == 17 => Ok(Self::Padded(key(&data[1..])?)),
+            Some(0xee) if data.len() > 17 && data.len() <= 17 + MAX_DOMAIN_LEN => {
+                Ok(Self::FakeTls(
+                    key(&data[1..])?,
+                    String::from_utf8(data[17..].to_vec())
+                        .map_err(|_| ClientError::InvalidProxySecret)?,
+                ))
+            }
+            _ => Err(ClientError::InvalidProxySecret),
+        }
+    }
+
+    /// Returns the 16-byte key.
+    pub fn key(&self) -> &[u8; 16] {
+        match self {
+            Self::Plain(key) => key,
+            Self::Padded(key) => key,
+            Self::FakeTls(key, _) => key,
+        }
+    }
+
+    /// Returns the transport type required by the secret.
+    pub fn transport_type(&self) -> Option<TransportType> {
+        match self {
+            Self::Plain(_) => None,
+            Self::Padded(_) | Self::FakeTls(_, _) => Some(TransportType::PaddedIntermediate),
+        }
+    }
+}
+
+impl FromStr for MtProxySecret {
+    type Err = ClientError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Decode a hex string.
+fn decode_hex(s: &str) -> Option<Vec<u8>> {
+    if !s.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..s.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(s.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// [MTProxy](https://core.telegram.org/mtproto/mtproto-transports#transport-obfuscation) server
+#[derive(Clone, Debug)]
+pub struct MtProxy {
+    /// The address of the proxy
+    address: String,
+    /// Secret
+    secret: MtProxySecret,
+}
+
+impl MtProxy {
+    /// Create a new proxy.
+    /// * `address` - The address of the proxy, e.g. `example.com:443`
+    /// * `secret` - The secret in hex or base64
+    pub fn new<S: Into<String>>(address: S, secret: &str) -> Result<Self, ClientError> {
+        Ok(Self {
+            address: address.into(),
+            secret: MtProxySecret::parse(secret)?,
+        })
+    }
+
+    /// Returns the address of the proxy.
+    pub fn address(&self) -> &str {
+        &self.address
+    }
+
+    /// Returns the secret.
+    pub fn secret(&self) -> &MtProxySecret {
+        &self.secret
+    }
+}
+
+/// Calculate HMAC-SHA256.
+fn hmac_sha256(key: &[u8], data: &[&[u8]]) -> Result<Vec<u8>, ErrorStack> {
+    let key = PKey::hmac(key)?;
+    let mut signer = Signer::new(MessageDigest::sha256(), &key)?;
+    for v in data {
+        signer.update(v)?;
+    }
+    signer.sign_to_vec()
+}
+
+/// Append a TLS extension.
+fn push_extension(hello: &mut Vec<u8>, ty: u16, data: &[u8]) {
+    hello.extend_from_slice(&ty.to_be_bytes());
+    hello.extend_from_slice(&(data.len() as u16).to_be_bytes());
+    hello.extend_from_slice(data);
+}
+
+/// Generate fake TLS ClientHello. The random field is
+/// `HMAC-SHA256(key, hello)` whose last 4 bytes are XORed with the current unix time.
+pub fn client_hello(key: &[u8; 16], domain: &str) -> Result<Vec<u8>, ErrorStack> {
+    let mut random = [0u8; 64];
+    openssl::rand::rand_bytes(&mut random)?;
+    let mut hello = Vec::with_capacity(CLIENT_HELLO_LEN);
+    hello.extend_from_slice(&[
+        0x16, 0x03, 0x01, 0x02, 0x00, 0x01, 0x00, 0x01, 0xfc, 0x03, 0x03,
+    ]);
+    hello.extend_from_slice(&[0; 32]);
+    hello.push(0x20);
+    hello.extend_from_slice(&random[0..32]);
+    let cipher_suites = [
+        0x0a, 0x0a, 0x13, 0x01, 0x13, 0x02, 0x13, 0x03, 0xc0, 0x2b, 0xc0, 0x2f, 0xc0, 0x2c, 0xc0,
+        0x30, 0xcc, 0xa9, 0xcc, 0xa8, 0xc0, 0x13, 0xc0, 0x14, 0x00, 0x9c, 0x00, 0x9d, 0x00, 0x2f,
+        0x00, 0x35,
+    ];
+    hello.extend_from_slice(&(cipher_suites.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&cipher_suites);
+    hello.extend_from_slice(&[0x01, 0x00]);
+    let mut extensions = Vec::new();
+    let mut server_name = Vec::new();
+    server_name.extend_from_slice(&(domain.len() as u16 + 3).to_be_bytes());
+    server_name.push(0);
+    server_name.extend_from_slice(&(domain.len() as u16).to_be_bytes());
+    server_name.extend_from_slice(domain.as_bytes());
+    push_extension(&mut extensions, 0x0000, &server_name);
+    push_extension(&mut extensions, 0x0017, &[]);
+    push_extension(&mut extensions, 0xff01, &[0x00]);
+    push_extension(
+        &mut extensions,
+        0x000a,
+        &[0x00, 0x08, 0x0a, 0x0a, 0x00, 0x1d, 0x00, 0x17, 0x00, 0x18],
+    );
+    push_extension(&mut extensions, 0x000b, &[0x01, 0x00]);
+    push_extension(
+        &mut extensions,
+        0x000d,
+        &[
+            0x00, 0x10, 0x04, 0x03, 0x08, 0x04, 0x04, 0x01, 0x05, 0x03, 0x08, 0x05, 0x05, 0x01,
+            0x08, 0x06, 0x06, 0x01,
+        ],
+    );
+    push_extension(
+        &mut extensions,
+        0x0010,
+        &[
+            0x00, 0x0c, 0x02, 0x68, 0x32, 0x08, 0x68, 0x74, 0x74, 0x70, 0x2f, 0x31, 0x2e, 0x31,
+        ],
+    );
+    let mut key_share = vec![0x00, 0x24, 0x00, 0x1d, 0x00, 0x20];
+    key_share.extend_from_slice(&random[32..64]);
+    push_extension(&mut extensions, 0x0033, &key_share);
+    push_extension(&mut extensions, 0x002b, &[0x04, 0x03, 0x04, 0x03, 0x03]);
+    // Pad to the fixed length with the padding extension.
+    let padding = CLIENT_HELLO_LEN.saturating_sub(hello.len() + 2 + extensions.len() + 4);
+    push_extension(&mut extensions, 0x0015, &vec![0; padding]);
+    hello.extend_from_slice(&(extensions.len() as u16).to_be_bytes());
+    hello.extend_from_slice(&extensions);
+    let digest = hmac_sha256(key, &[&hello])?;
+    hello[DIGEST_OFFSET..DIGEST_OFFSET + 32].copy_from_slice(&digest);
+    let now = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs() as u32;
+    for (v, t) in hello[DIGEST_OFFSET + 28..DIGEST_OFFSET + 32]
+        .iter_mut()
+        .zip(now.to_le_bytes())
+    {
+        *v ^= t;
+    }
+    Ok(hello)
+}
+
+/// Verify the response of fake TLS ClientHello. The random field of ServerHello is
+/// `HMAC-SHA256(key, client_random + response)` where the random field is zeroed.
+/// * `hello` - ClientHello sent to the proxy
+/// * `response` - ServerHello, ChangeCipherSpec and the first application data record
+pub fn verify_server_hello(
+    key: &[u8; 16],
+    hello: &[u8],
+    response: &[u8],
+) -> Result<bool, ErrorStack> {
+    if response.len() < DIGEST_OFFSET + 32 {
+        return Ok(false);
+    }
+    let mut zeroed = response.to_vec();
+    zeroed[DIGEST_OFFSET..DIGEST_OFFSET + 32].fill(0);
+    let digest = hmac_sha256(key, &[&hello[DIGEST_OFFSET..DIGEST_OFFSET + 32], &zeroed])?;
+    Ok(memcmp::eq(
+        &digest,
+        &response[DIGEST_OFFSET..DIGEST_OFFSET + 32],
+    ))
+}
+
+#[test]
+fn test_parse_secret() {
+    let key = [
+        0x00, 0x11, 0x22, 0x33, 0x44, 0x55, 0x66, 0x77, 0x88, 0x99, 0xaa, 0xbb, 0xcc, 0xdd, 0xee,
+        0xff,
+    ];
+    assert_eq!(
+        MtProxySecret::parse("00112233445566778899aabbccddeeff").unwrap(),
+        MtProxySecret::Plain(key)
+    );
+    assert_eq!(
+        MtProxySecret::parse("dd00112233445566778899AABBCCDDEEFF").unwrap(),
+        MtProxySecret::Padded(key)
+    );
+    let secret =
+        MtProxySecret::parse("ee00112233445566778899aabbccddeeff6578616d706c652e636f6d").unwrap();
+    assert_eq!(
+        secret,
+        MtProxySecret::FakeTls(key, "example.com".to_string())
+    );
+    assert_eq!(
+        MtProxySecret::parse("7gARIjNEVWZ3iJmqu8zd7v9leGFtcGxlLmNvbQ").unwrap(),
+        secret
+    );
+    assert_eq!(
+        secret.transport_type(),
+        Some(TransportType::PaddedIntermediate)
+    );
+    assert!(MtProxySecret::parse("0011").is_err());
+    let hello = client_hello(&key, "example.com").unwrap();
+    assert_eq!(hello.len(), CLIENT_HELLO_LEN);
+    assert!(hello
+        .windows("example.com".len())
+        .any(|v| v == b"example.com"));
+    let domain = "a".repeat(MAX_DOMAIN_LEN);
+    let secret = format!(
+        "ee00112233445566778899aabbccddeeff{}",
+        "61".repeat(MAX_DOMAIN_LEN)
+    );
+    assert_eq!(
+        MtProxySecret::parse(&secret).unwrap(),
+        MtProxySecret::FakeTls(key, domain.clone())
+    );
+    assert!(MtProxySecret::parse(&format!("{secret}61")).is_err());
+    assert_eq!(client_hello(&key, &domain).unwrap().len(), CLIENT_HELLO_LEN);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_fake_tls_proxy() {
+    use super::obfuscation::AesCtr;
+    use super::ClientBuilder;
+    use openssl::sha::Sha256;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    let key = [7u8; 16];
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    // A stand-in proxy which echoes packets
+    let proxy = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut hello = vec![0u8; CLIENT_HELLO_LEN];
+        stream.read_exact(&mut hello).await.unwrap();
+        let mut zeroed = hello.clone();
+        zeroed[DIGEST_OFFSET..DIGEST_OFFSET + 32].fill(0);
+        let digest = hmac_sha256(&key, &[&zeroed]).unwrap();
+        assert_eq!(digest[..28], hello[DIGEST_OFFSET..DIGEST_OFFSET + 28]);
+        let mut response = vec![0x16, 0x03, 0x03, 0x00, 0x40];
+        response.extend_from_slice(&[0x02; 0x40]);
+        response.extend_from_slice(&CHANGE_CIPHER_SPEC);
+        response.extend_from_slice(&[0x17, 0x03, 0x03, 0x00, 0x20]);
+        response.extend_from_slice(&[0x03; 0x20]);
+        response[DIGEST_OFFSET..DIGEST_OFFSET + 32].fill(0);
+        let digest = hmac_sha256(
+            &key,
+            &[&hello[DIGEST_OFFSET..DIGEST_OFFSET + 32], &response],
+        )
+        .unwrap();
+        response[DIGEST_OFFSET..DIGEST_OFFSET + 32].copy_from_slice(&digest);
+        stream.write_all(&response).await.unwrap();
+        let mut ccs = [0u8; 6];
+        stream.read_exact(&mut ccs).await.unwrap();
+        assert_eq!(ccs, CHANGE_CIPHER_SPEC);
+        let mut records = Vec::new();
+        for _ in 0..2 {
+            let mut header = [0u8; 5];
+            stream.read_exact(&mut header).await.unwrap();
+            assert_eq!(header[0..3], [0x17, 0x03, 0x03]);
+            let mut data = vec![0u8; u16::from_be_bytes([header[3], header[4]]) as usize];
+            stream.read_exact(&mut data).await.unwrap();
+            records.push(data);
+        }
+        let init = &records[0];
+        let derive = |v: &[u8]| {
+            let mut hasher = Sha256::new();
+            hasher.update(v);
+            hasher.update(&key);
+            hasher.finish()
+        };
+        let reversed = init[8..56].iter().rev().copied().collect::<Vec<_>>();
+        let mut decryptor = AesCtr::new(&derive(&init[8..40]), &init[40..56]).unwrap();
+        let mut encryptor = AesCtr::new(&derive(&reversed[0..32]), &reversed[32..48]).unwrap();
+        let mut decrypted = init.clone();
+        decryptor.apply(&mut decrypted).unwrap();
+        assert_eq!(decrypted[56..60], [0xdd; 4]);
+        assert_eq!(decrypted[60..62], 2i16.to_le_bytes());
+        let mut packet = records[1].clone();
+        decryptor.apply(&mut packet).unwrap();
+        assert_eq!(&packet[4..12], b"01234567");
+        let mut answer = 8u32.to_le_bytes().to_vec();
+        answer.extend_from_slice(b"76543210");
+        encryptor.apply(&mut answer).unwrap();
+        let mut record = vec![0x17, 0x03, 0x03, 0x00, answer.len() as u8];
+        record.extend_from_slice(&answer);
+        stream.write_all(&record).await.unwrap();
+    });
+    let secret = format!(
+        "ee{}{}",
+        "07".repeat(16),
+        "example.com"
+            .bytes()
+            .map(|v| format!("{:02x}", v))
+            .collect::<String>()
+    );
+    let client = ClientBuilder::new()
+        .mtproxy(MtProxy::new(address.to_string(), &secret).unwrap())
+        .dc_id(2)
+        .build("0.0.0.0:0")
+        .await
+        .unwrap();
+    client.send(b"01234567".to_vec()).await.unwrap();
+    assert_eq!(&client.recv().await.unwrap()[..], b"76543210");
+    proxy.await.unwrap();
+}
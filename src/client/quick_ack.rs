@@ -0,0 +1,57 @@
+use super::ClientError;
+use std::collections::HashMap;
+use std::future::Future;
+use std::pin::Pin;
+use std::sync::Mutex;
+use std::task::{Context, Poll};
+use tokio::sync::oneshot;
+
+/// A future which resolves when server confirms the receipt of a message with quick ACK.
+/// It resolves to [ClientError::Disconnected] if the client is dropped before that.
+/// [More](https://core.telegram.org/mtproto/mtproto-transports#quick-ack)
+#[derive(Debug)]
+pub struct QuickAck(oneshot::Receiver<()>);
+
+impl QuickAck {
+    /// Returns a [QuickAck] which resolves to [ClientError::Disconnected] at once,
+    /// e.g. for a message which is not sent because the connection is broken.
+    pub(crate) fn disconnected() -> Self {
+        Self(oneshot::channel().1)
+    }
+}
+
+impl Future for QuickAck {
+    type Output = Result<(), ClientError>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        Pin::new(&mut self.0)
+            .poll(cx)
+            .map(|v| v.map_err(|_| ClientError::Disconnected))
+    }
+}
+
+/// Quick ACK tokens which are not received yet
+#[derive(Debug, Default)]
+pub struct QuickAcks {
+    /// The senders of pending [QuickAck] by token
+    pending: Mutex<HashMap<u32, oneshot::Sender<()>>>,
+}
+
+impl QuickAcks {
+    /// Wait for a token.
+    pub fn register(&self, token: u32) -> QuickAck {
+        let (sender, receiver) = oneshot::channel();
+        let mut pending = self.pending.lock().unwrap();
+        // Forget the tokens nobody waits for.
+        pending.retain(|_, v| !v.is_closed());
+        pending.insert(token, sender);
+        QuickAck(receiver)
+    }
+
+    /// Resolve the [QuickAck] of a received token.
+    pub fn resolve(&self, token: u32) {
+        if let Some(sender) = self.pending.lock().unwrap().remove(&token) {
+            sender.send(()).ok();
+        }
+    }
+}
@@ -0,0 +1,399 @@
+use super::{
+    AuthKeyError, ClientBuilder, ClientError, ConnectionState, EncryptedClient, Recovery, Session,
+    TransportError,
+};
+use crate::objects::traits::Function;
+use rand::{Rng, SeedableRng};
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::sync::watch;
+use tokio::task::JoinHandle;
+
+/// A DC which [ReconnectingClient] can connect to
+#[derive(Clone, Debug)]
+pub struct DcOption {
+    /// The DC id. It is sent in the obfuscated init payload and used to generate authorization keys.
+    pub id: i16,
+    /// The endpoints of the DC. They are raced as in [ClientBuilder::build_any].
+    pub endpoints: Vec<SocketAddr>,
+}
+
+/// Shared state of [ReconnectingClient]
+struct Shared {
+    /// The options of new connections
+    builder: ClientBuilder,
+    /// The DCs to connect to. The next one is used when server reports an invalid DC.
+    dcs: Vec<DcOption>,
+    /// The index of the current DC in `dcs`
+    dc: AtomicUsize,
+    /// The current connection
+    current: Mutex<Arc<EncryptedClient>>,
+    /// Publishes the connection state
+    state: watch::Sender<ConnectionState>,
+}
+
+impl Shared {
+    /// Returns the current connection.
+    fn current(&self) -> Arc<EncryptedClient> {
+        self.current.lock().unwrap().clone()
+    }
+
+    /// Wait until the current connection is closed and resume its session over a new one.
+    /// Returns when the connection can not be resumed.
+    async fn supervise(&self) {
+        loop {
+            let previous = self.current();
+            previous.closed().await;
+            let client = previous.client();
+            let mut error = previous.transport_error();
+            let mut rekey = false;
+            self.state.send_replace(ConnectionState::Reconnecting);
+            let mut attempt = 0;
+            loop {
+                // The error may come from the closed connection or from the last attempt.
+                let recovery = error.map(|v| v.recovery());
+                match recovery {
+                    Some(Recovery::RegenerateAuthKey) => rekey = true,
+                    // Authorization keys are bound to DCs, so another DC needs a new one too.
+                    Some(Recovery::SwitchDc) => {
+                        let dc = self.dc.load(Ordering::SeqCst) + 1;
+                        if dc >= self.dcs.len() {
+                            self.give_up(&previous, error);
+                            return;
+                        }
+                        self.dc.store(dc, Ordering::SeqCst);
+                        rekey = true;
+                    }
+                    _ => {}
+                }
+                if rekey && client.public_keys().is_empty() {
+                    self.give_up(&previous, error);
+                    return;
+                }
+                let delay = if recovery == Some(Recovery::Backoff) {
+                    client.max_reconnect_delay()
+                } else {
+                    backoff(
+                        client.reconnect_delay(),
+                        client.max_reconnect_delay(),
+                        attempt,
+                    )
+                };
+                tokio::time::sleep(jitter(delay)).await;
+                match self.resume(&previous, rekey).await {
+                    Ok(current) => {
+                        *self.current.lock().unwrap() = Arc::new(current);
+                        self.state.send_replace(ConnectionState::Ready);
+                        break;
+                    }
+                    Err(AuthKeyError::ClientError(ClientError::TransportError(e))) => {
+                        error = Some(e)
+                    }
+                    Err(AuthKeyError::ClientError(e)) if e.is_connection_error() => error = None,
+                    // Trying again would fail the same way.
+                    Err(_) => {
+                        self.give_up(&previous, None);
+                        return;
+                    }
+                }
+                attempt += 1;
+                if client.max_reconnect_attempts() == Some(attempt) {
+                    self.give_up(&previous, error);
+                    return;
+                }
+            }
+        }
+    }
+
+    /// Publish [ConnectionState::Closed] and fail pending requests.
+    /// * `error` - The transport error which pending requests fail with
+    fn give_up(&self, previous: &EncryptedClient, error: Option<TransportError>) {
+        self.state.send_replace(ConnectionState::Closed);
+        previous.abandon(error);
+    }
+
+    /// Connect to the current DC again over a new stream whose transport is initialized,
+    /// and resume the session of the previous connection.
+    /// * `rekey` - Whether to generate a new authorization key and continue in a new session
+    async fn resume(
+        &self,
+        previous: &EncryptedClient,
+        rekey: bool,
+    ) -> Result<EncryptedClient, AuthKeyError> {
+        let dc = &self.dcs[self.dc.load(Ordering::SeqCst)];
+        let client = self
+            .builder
+            .clone()
+            .dc_id(dc.id)
+            .build_any(&dc.endpoints)
+            .await?;
+        if !rekey {
+            return Ok(EncryptedClient::resume(client, previous).await?);
+        }
+        let generated = client
+            .generate_auth_key(client.public_keys(), dc.id as i32, None)
+            .await?;
+        let session = Session::new(generated.auth_key, generated.server_salt);
+        Ok(EncryptedClient::resume_in(client, previous, session).await?)
+    }
+}
+
+/// The delay before a reconnection attempt, which is doubled after each failed attempt.
+/// * `initial` - The delay before the first attempt
+/// * `max` - The maximum delay
+/// * `attempt` - The number of failed attempts
+fn backoff(initial: Duration, max: Duration, attempt: u32) -> Duration {
+    initial
+        .saturating_mul(2u32.saturating_pow(attempt))
+        .min(max)
+}
+
+/// Randomize the delay between a half of it and itself,
+/// so that clients disconnected at once do not connect again at once.
+fn jitter(delay: Duration) -> Duration {
+    delay / 2 + delay.mul_f64(rand::rngs::StdRng::from_entropy().gen_range(0.0..0.5))
+}
+
+/// [EncryptedClient] which connects again when the connection is closed.
+///
+/// The auth key and the session are kept across connections and the messages which are
+/// not acknowledged are resent, so pending requests complete over the new connection.
+/// Reconnection attempts are delayed with exponential backoff and jitter,
+/// see [ClientBuilder::reconnect_delay] and [ClientBuilder::max_reconnect_delay].
+/// When server does not know the authorization key, a new one is generated with
+/// [ClientBuilder::public_keys] and the requests continue in a new session, see [Recovery].
+/// When server reports an invalid DC, the next of the given DCs is used likewise.
+/// It gives up if no public key is set or no DC is left, after [ClientBuilder::max_reconnect_attempts]
+/// failed attempts, or when an attempt fails with an error which is not a connection error,
+/// and pending requests fail then.
+/// The new authorization key is available from [ReconnectingClient::current].
+pub struct ReconnectingClient {
+    /// Shared state
+    shared: Arc<Shared>,
+    /// The background task which resumes closed connections
+    supervisor: JoinHandle<()>,
+}
+
+impl ReconnectingClient {
+    /// Connect to the first DC and start the session.
+    /// Must be called within a tokio runtime.
+    /// * `builder` - The options of every connection
+    /// * `dcs` - The DCs to connect to. The others are used in order when server reports an invalid DC.
+    /// * `session` - The session
+    pub async fn connect(
+        builder: ClientBuilder,
+        dcs: &[DcOption],
+        session: Session,
+    ) -> Result<Self, ClientError> {
+        let dc = dcs.first().ok_or_else(|| {
+            ClientError::TokioError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "No DC is given.",
+            ))
+        })?;
+        let (state, _) = watch::channel(ConnectionState::Connecting);
+        let client = builder.clone().dc_id(dc.id).build_any(&dc.endpoints).await;
+        let client = match client {
+            Ok(client) => EncryptedClient::supervised(client, session).await,
+            Err(e) => Err(e),
+        };
+        let client = match client {
+            Ok(client) => client,
+            Err(e) => {
+                state.send_replace(ConnectionState::Closed);
+                return Err(e);
+            }
+        };
+        state.send_replace(ConnectionState::Ready);
+        let shared = Arc::new(Shared {
+            builder,
+            dcs: dcs.to_vec(),
+            dc: AtomicUsize::new(0),
+            current: Mutex::new(Arc::new(client)),
+            state,
+        });
+        let supervisor = tokio::spawn({
+            let shared = shared.clone();
+            async move { shared.supervise().await }
+        });
+        Ok(Self { shared, supervisor })
+    }
+
+    /// Returns a receiver of the connection state. It is [ConnectionState::Reconnecting]
+    /// while the session is being resumed and [ConnectionState::Closed] once it is given up.
+    pub fn state(&self) -> watch::Receiver<ConnectionState> {
+        self.shared.state.subscribe()
+    }
+
+    /// Returns the current connection.
+    pub fn current(&self) -> Arc<EncryptedClient> {
+        self.shared.current()
+    }
+
+    /// Invoke a function and wait for its result. The request is sent again over
+    /// the new connection if the current one is closed before the result is received.
+    /// * `function` - The function
+    pub async fn invoke<F: Function>(&self, function: &F) -> Result<F::Return, ClientError> {
+        let mut state = self.state();
+        loop {
+            let client = self.current();
+            match client.invoke(function).await {
+                // The request is not sent because the connection is closed.
+                Err(ClientError::Disconnected) if client.is_closed() => {}
+                result => return result,
+            }
+            // Wait until the connection is resumed or given up.
+            loop {
+                if *state.borrow_and_update() == ConnectionState::Closed {
+                    return Err(ClientError::Disconnected);
+                }
+                if !Arc::ptr_eq(&client, &self.current()) {
+                    break;
+                }
+                state
+                    .changed()
+                    .await
+                    .map_err(|_| ClientError::Disconnected)?;
+            }
+        }
+    }
+}
+
+impl Drop for ReconnectingClient {
+    fn drop(&mut self) {
+        self.supervisor.abort();
+        self.shared.state.send_replace(ConnectionState::Closed);
+        self.shared.current().abandon(None);
+    }
+}
+
+#[test]
+fn test_backoff() {
+    let (initial, max) = (Duration::from_secs(1), Duration::from_secs(60));
+    assert_eq!(backoff(initial, max, 0), initial);
+    assert_eq!(backoff(initial, max, 3), Duration::from_secs(8));
+    assert_eq!(backoff(initial, max, 6), max);
+    assert_eq!(backoff(initial, max, 100), max);
+    for _ in 0..100 {
+        let delay = jitter(max);
+        assert!(delay >= max / 2 && delay <= max);
+    }
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_reconnect() {
+    use super::test_server::{test_auth_key, test_builder, FakeServer};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+    use crate::objects::mtproto_api::types::ResPQ;
+
+    let server = FakeServer::bind().await;
+    let dcs = [DcOption {
+        id: 2,
+        endpoints: vec![server.address()],
+    }];
+    let server = tokio::spawn(async move {
+        // Drop the first connection after the request is received.
+        let request = server.accept().await.read().await;
+        let mut connection = server.accept().await;
+        let resent = connection.read().await;
+        assert_eq!(resent.session_id, request.session_id);
+        assert!(resent.message_id > request.message_id);
+        assert_eq!(resent.payload, request.payload);
+        connection.answer(&resent).await;
+        connection
+    });
+    let builder = test_builder().reconnect_delay(Duration::from_millis(10));
+    let client = ReconnectingClient::connect(builder, &dcs, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    let mut state = client.state();
+    let previous = client.current();
+    let request = req_pq_multi::new();
+    let ResPQ::ResPQ(result) = client.invoke(&request).await.unwrap();
+    assert_eq!(result.nonce, request.nonce);
+    assert!(previous.is_closed());
+    // The result may be received before the new connection is published.
+    state
+        .wait_for(|v| *v == ConnectionState::Ready)
+        .await
+        .unwrap();
+    assert!(!Arc::ptr_eq(&previous, &client.current()));
+    let _connection = server.await.unwrap();
+    drop(client);
+    assert_eq!(*state.borrow_and_update(), ConnectionState::Closed);
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_transport_error_recovery() {
+    use super::test_server::{test_auth_key, test_builder, FakeServer};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+    use crate::objects::mtproto_api::types::RSAPublicKey;
+    use crate::objects::traits::TypeId;
+    use bytes::BytesMut;
+
+    let server = FakeServer::bind().await;
+    let dcs = [DcOption {
+        id: 2,
+        endpoints: vec![server.address()],
+    }];
+    let server = tokio::spawn(async move {
+        let mut connection = server.accept().await;
+        connection.read().await;
+        connection.write_transport_error(-404).await;
+        // A new authorization key is being generated.
+        let mut connection = server.accept().await;
+        let data = connection.read_frame().await;
+        assert_eq!(&data[..8], &[0; 8]);
+        assert_eq!(&data[20..24], &req_pq_multi::type_id2().to_le_bytes());
+        connection.write_transport_error(-444).await;
+    });
+    let builder = test_builder()
+        .reconnect_delay(Duration::from_millis(10))
+        .public_keys(vec![RSAPublicKey {
+            n: BytesMut::from(&[1u8][..]),
+            e: BytesMut::from(&[1u8][..]),
+        }]);
+    let client = ReconnectingClient::connect(builder, &dcs, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    // -404 starts generating a new key, and -444 gives up because no other DC is left.
+    assert!(matches!(
+        client.invoke(&req_pq_multi::new()).await,
+        Err(ClientError::TransportError(TransportError::InvalidDc))
+    ));
+    assert_eq!(*client.state().borrow(), ConnectionState::Closed);
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_max_reconnect_attempts() {
+    use super::test_server::{test_auth_key, test_builder, FakeServer};
+    use crate::objects::mtproto_api::functions::req_pq_multi;
+
+    let server = FakeServer::bind().await;
+    let dcs = [DcOption {
+        id: 2,
+        endpoints: vec![server.address()],
+    }];
+    let server = tokio::spawn(async move {
+        server.accept().await.read().await;
+        // Later connections are refused.
+    });
+    let builder = test_builder()
+        .reconnect_delay(Duration::from_millis(10))
+        .max_reconnect_attempts(2);
+    let client = ReconnectingClient::connect(builder, &dcs, Session::new(test_auth_key(), 0))
+        .await
+        .unwrap();
+    assert!(matches!(
+        client.invoke(&req_pq_multi::new()).await,
+        Err(ClientError::Disconnected)
+    ));
+    assert_eq!(*client.state().borrow(), ConnectionState::Closed);
+    server.await.unwrap();
+}
@@ -0,0 +1,150 @@
+use super::{AuthKey, ClientError};
+use crate::objects::base::DecryptedMessage;
+use crate::objects::mtproto_api::constructors::{message, new_session_created};
+use crate::objects::traits::{Deserialize, TypeId};
+use bytes::BytesMut;
+use rand::{Rng, SeedableRng};
+use std::sync::atomic::{AtomicI32, AtomicI64, Ordering};
+
+/// MTProto session. [More](https://core.telegram.org/mtproto/description#session)
+///
+/// It owns the authorization key, the session id, the current server salt and the message sequence number.
+#[derive(Debug)]
+pub struct Session {
+    /// The authorization key
+    auth_key: AuthKey,
+    /// A (random) 64-bit number generated by the client to distinguish between individual sessions.
+    session_id: AtomicI64,
+    /// Current server salt
+    server_salt: AtomicI64,
+    /// The number of content-related messages sent in this session.
+    content_count: AtomicI32,
+}
+
+impl Session {
+    /// Create a new session with a random session id.
+    /// * `auth_key` - The authorization key
+    /// * `server_salt` - The server salt
+    pub fn new(auth_key: AuthKey, server_salt: i64) -> Self {
+        Self {
+            auth_key,
+            session_id: AtomicI64::new(rand::rngs::StdRng::from_entropy().gen()),
+            server_salt: AtomicI64::new(server_salt),
+            content_count: AtomicI32::new(0),
+        }
+    }
+
+    /// Returns the authorization key.
+    pub fn auth_key(&self) -> &AuthKey {
+        &self.auth_key
+    }
+
+    /// Returns the session id.
+    pub fn session_id(&self) -> i64 {
+        self.session_id.load(Ordering::SeqCst)
+    }
+
+    /// Returns the current server salt.
+    pub fn server_salt(&self) -> i64 {
+        self.server_salt.load(Ordering::SeqCst)
+    }
+
+    /// Set the server salt.
+    pub fn set_server_salt(&self, server_salt: i64) {
+        self.server_salt.store(server_salt, Ordering::SeqCst)
+    }
+
+    /// Start a new session with a new random session id.
+    pub fn reset(&self) {
+        self.session_id
+            .store(rand::rngs::StdRng::from_entropy().gen(), Ordering::SeqCst);
+        self.content_count.store(0, Ordering::SeqCst);
+    }
+
+    /// Returns the message sequence number of the next message:
+    /// `2n + 1` for content-related messages, `2n` otherwise,
+    /// where `n` is the number of content-related messages sent before.
+    /// [More](https://core.telegram.org/mtproto/description#message-sequence-number-msg-seqno)
+    pub fn next_seq_no(&self, content_related: bool) -> i32 {
+        if content_related {
+            self.content_count.fetch_add(1, Ordering::SeqCst) * 2 + 1
+        } else {
+            self.content_count.load(Ordering::SeqCst) * 2
+        }
+    }
+
+    /// Stamp an outgoing message with the session id, the current server salt and the next sequence number.
+    /// * `message_id` - Message id
+    /// * `payload` - Serialized message body
+    /// * `content_related` - Whether the message requires an explicit acknowledgment.
+    pub fn pack(
+        &self,
+        message_id: i64,
+        payload: BytesMut,
+        content_related: bool,
+    ) -> DecryptedMessage {
+        DecryptedMessage {
+            salt: self.server_salt(),
+            session_id: self.session_id(),
+            message_id,
+            seq_no: self.next_seq_no(content_related),
+            payload,
+        }
+    }
+
+    /// Check an incoming message, unpack containers and `gzip_packed` objects in it
+    /// and update the session state from service messages.
+    /// Returns the unpacked messages.
+    pub fn handle_message(&self, message: &DecryptedMessage) -> Result<Vec<message>, ClientError> {
+        if message.session_id != self.session_id() {
+            return Err(ClientError::SessionIdMismatched);
+        }
+        let messages = message::from(message).unpack()?;
+        for message in &messages {
+            if message.body_type_id() == Some(new_session_created::type_id2()) {
+                let new_session = new_session_created::deserialize_from_bytes(&message.body[4..])?;
+                self.set_server_salt(new_session.server_salt);
+            }
+        }
+        Ok(messages)
+    }
+}
+
+#[test]
+fn test_seq_no() {
+    let session = Session::new(AuthKey::new([0; 256]), 1);
+    assert_eq!(session.next_seq_no(false), 0);
+    assert_eq!(session.next_seq_no(true), 1);
+    assert_eq!(session.next_seq_no(true), 3);
+    assert_eq!(session.next_seq_no(false), 4);
+    let message = session.pack(0, BytesMut::new(), true);
+    assert_eq!(message.seq_no, 5);
+    assert_eq!(message.salt, 1);
+    assert_eq!(message.session_id, session.session_id());
+    session.reset();
+    assert_eq!(session.next_seq_no(true), 1);
+}
+
+#[test]
+fn test_handle_message() {
+    use crate::objects::mtproto_api::constructors::new_session_created;
+    use crate::objects::traits::Serialize;
+    let session = Session::new(AuthKey::new([0; 256]), 1);
+    let mut message = session.pack(
+        0,
+        Box::new(new_session_created {
+            first_msg_id: 0,
+            unique_id: 2,
+            server_salt: 3,
+        })
+        .serialize_to_bytes(),
+        false,
+    );
+    assert_eq!(session.handle_message(&message).unwrap().len(), 1);
+    assert_eq!(session.server_salt(), 3);
+    message.session_id = session.session_id().wrapping_add(1);
+    assert!(matches!(
+        session.handle_message(&message),
+        Err(ClientError::SessionIdMismatched)
+    ));
+}
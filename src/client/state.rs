@@ -0,0 +1,17 @@
+/// The state of a connection. It is published through [tokio::sync::watch]
+/// by [Client::state](super::Client::state).
+#[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum ConnectionState {
+    /// Connecting to the server or the proxy
+    Connecting,
+    /// Sending the transport header, or doing the handshake with the proxy or the WebSocket server
+    TransportInit,
+    /// Generating the authorization key
+    Handshaking,
+    /// Ready to send and receive messages
+    Ready,
+    /// Waiting to connect again after the connection is closed
+    Reconnecting,
+    /// The connection is closed.
+    Closed,
+}
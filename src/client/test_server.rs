@@ -0,0 +1,215 @@
+use super::{AuthKey, Client, ClientBuilder, TransportType};
+use crate::aes::{decrypt_message, encrypt_message, Side};
+use crate::objects::base::{DecryptedMessage, EncryptedMessage};
+use crate::objects::mtproto_api::constructors::{bad_msg_notification, pong, resPQ, rpc_result};
+use crate::objects::traits::{Deserialize, Serialize};
+use bytes::BytesMut;
+use std::net::SocketAddr;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncWrite, AsyncWriteExt};
+use tokio::net::TcpListener;
+
+/// Returns the authorization key which the fake server and the clients in tests share.
+pub fn test_auth_key() -> AuthKey {
+    AuthKey::new([7; 256])
+}
+
+/// Returns a builder of clients which talk to the fake server.
+pub fn test_builder() -> ClientBuilder {
+    ClientBuilder::new().transport_type(TransportType::Abridged)
+}
+
+/// Build a client over an in-memory stream to the fake server.
+/// Nothing is sent until the client is initialized.
+/// * `builder` - The builder of the client, e.g. [test_builder] with more options
+pub fn connect(builder: ClientBuilder) -> (Client, FakeConnection) {
+    let (client, server) = tokio::io::duplex(1 << 16);
+    let client = builder.build_with_stream(client).unwrap();
+    (client, FakeConnection::new(server))
+}
+
+/// A stream between the fake server and a client
+pub trait TestStream: AsyncRead + AsyncWrite + Unpin + Send {}
+
+impl<T: AsyncRead + AsyncWrite + Unpin + Send> TestStream for T {}
+
+/// A fake server which accepts clients over TCP, e.g. to test reconnection.
+pub struct FakeServer {
+    /// The listener
+    listener: TcpListener,
+}
+
+impl FakeServer {
+    /// Listen on a random local port.
+    pub async fn bind() -> Self {
+        Self {
+            listener: TcpListener::bind("127.0.0.1:0").await.unwrap(),
+        }
+    }
+
+    /// Returns the address which the server listens on.
+    pub fn address(&self) -> SocketAddr {
+        self.listener.local_addr().unwrap()
+    }
+
+    /// Accept a client.
+    pub async fn accept(&self) -> FakeConnection {
+        let (stream, _) = self.listener.accept().await.unwrap();
+        FakeConnection::new(stream)
+    }
+}
+
+/// The server side of a connection with abridged transport.
+/// Messages are encrypted with [test_auth_key].
+pub struct FakeConnection {
+    /// The stream to the client
+    stream: Box<dyn TestStream>,
+    /// Whether the transport tag is received
+    initialized: bool,
+}
+
+impl FakeConnection {
+    /// Create a connection over a stream to the client.
+    fn new<S: TestStream + 'static>(stream: S) -> Self {
+        Self {
+            stream: Box::new(stream),
+            initialized: false,
+        }
+    }
+
+    /// Read a frame. Returns its payload.
+    /// The transport tag is checked before the first frame.
+    pub async fn read_frame(&mut self) -> Vec<u8> {
+        if !self.initialized {
+            assert_eq!(self.stream.read_u8().await.unwrap(), 0xef);
+            self.initialized = true;
+        }
+        let mut le = [0u8; 4];
+        self.stream.read_exact(&mut le[..1]).await.unwrap();
+        if le[0] == 0x7f {
+            self.stream.read_exact(&mut le[..3]).await.unwrap();
+        }
+        let mut data = vec![0u8; u32::from_le_bytes(le) as usize * 4];
+        self.stream.read_exact(&mut data).await.unwrap();
+        data
+    }
+
+    /// Read an encrypted message and decrypt it.
+    pub async fn read(&mut self) -> DecryptedMessage {
+        let data = self.read_frame().await;
+        let message = EncryptedMessage::deserialize_from_bytes(&data).unwrap();
+        let data = decrypt_message(
+            test_auth_key().as_bytes(),
+            &message.msg_key,
+            &message.encrypted_data,
+            Side::Client,
+        )
+        .unwrap();
+        DecryptedMessage::deserialize_from_bytes(&data).unwrap()
+    }
+
+    /// Write a frame.
+    pub async fn write_frame(&mut self, data: &[u8]) {
+        let mut frame = vec![0x7f];
+        frame.extend_from_slice(&((data.len() / 4) as u32).to_le_bytes()[..3]);
+        frame.extend_from_slice(data);
+        self.stream.write_all(&frame).await.unwrap();
+    }
+
+    /// Encrypt a message and write it.
+    pub async fn write(&mut self, message: &DecryptedMessage) {
+        let auth_key = test_auth_key();
+        let (msg_key, encrypted_data) = encrypt_message(
+            auth_key.as_bytes(),
+            &message.serialize_to_bytes(),
+            Side::Server,
+        )
+        .unwrap();
+        let data = EncryptedMessage {
+            auth_key_id: auth_key.id(),
+            msg_key,
+            encrypted_data,
+        }
+        .serialize_to_vec();
+        self.write_frame(&data).await;
+    }
+
+    /// Write a content-related message right after a message of the client, in its session.
+    /// * `request` - The message of the client
+    /// * `payload` - Serialized message body
+    pub async fn reply(&mut self, request: &DecryptedMessage, payload: BytesMut) {
+        self.write(&DecryptedMessage {
+            salt: request.salt,
+            session_id: request.session_id,
+            message_id: request.message_id + 1,
+            seq_no: 1,
+            payload,
+        })
+        .await;
+    }
+
+    /// Answer `req_pq_multi` with `resPQ`.
+    /// * `request` - The message of the client
+    pub async fn answer(&mut self, request: &DecryptedMessage) {
+        self.reply(request, res_pq(request)).await;
+    }
+
+    /// Write a transport error.
+    /// * `code` - The error code, e.g. -404
+    pub async fn write_transport_error(&mut self, code: i32) {
+        let mut frame = vec![0x01];
+        frame.extend_from_slice(&code.to_le_bytes());
+        self.stream.write_all(&frame).await.unwrap();
+    }
+}
+
+/// Returns a serialized `rpc_result` with `resPQ` whose nonce is the one of `req_pq_multi` in the request.
+pub fn res_pq(request: &DecryptedMessage) -> BytesMut {
+    let nonce = i128::deserialize_from_bytes(&request.payload[4..]).unwrap();
+    Box::new(rpc_result {
+        req_msg_id: request.message_id,
+        result: Box::new(resPQ {
+            nonce,
+            server_nonce: 0,
+            pq: BytesMut::new(),
+            server_public_key_fingerprints: Box::new(Vec::new()),
+        })
+        .serialize_to_bytes(),
+    })
+    .serialize_to_bytes()
+}
+
+/// Returns a `bad_msg_notification` from server about the request.
+/// * `request` - The message of the client
+/// * `message_id` - The message id of the notification
+/// * `code` - The error code
+pub fn bad_msg(request: &DecryptedMessage, message_id: i64, code: i32) -> DecryptedMessage {
+    DecryptedMessage {
+        salt: request.salt,
+        session_id: request.session_id,
+        message_id,
+        seq_no: 0,
+        payload: Box::new(bad_msg_notification {
+            bad_msg_id: request.message_id,
+            bad_msg_seqno: request.seq_no,
+            error_code: code,
+        })
+        .serialize_to_bytes(),
+    }
+}
+
+/// Returns a content-related message from server which the client has to acknowledge.
+/// * `session_id` - The session id of the client
+/// * `message_id` - The message id
+pub fn unsolicited(session_id: i64, message_id: i64) -> DecryptedMessage {
+    DecryptedMessage {
+        salt: 0,
+        session_id,
+        message_id,
+        seq_no: 1,
+        payload: Box::new(pong {
+            msg_id: 0,
+            ping_id: 0,
+        })
+        .serialize_to_bytes(),
+    }
+}
@@ -0,0 +1,307 @@
+use super::ClientError;
+use base64::Engine;
+use std::net::IpAddr;
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+
+/// A proxy which tunnels the TCP connection to the server.
+#[derive(Clone, Debug)]
+pub enum Proxy {
+    /// [SOCKS5](https://www.rfc-editor.org/rfc/rfc1928) proxy
+    Socks5 {
+        /// The address of the proxy
+        address: String,
+        /// Username and password
+        auth: Option<(String, String)>,
+    },
+    /// HTTP proxy which supports `CONNECT`
+    Http {
+        /// The address of the proxy
+        address: String,
+        /// Username and password for basic authentication
+        auth: Option<(String, String)>,
+    },
+}
+
+impl Proxy {
+    /// Create a SOCKS5 proxy without authentication.
+    /// * `address` - The address of the proxy, e.g. `127.0.0.1:1080`
+    pub fn socks5<S: Into<String>>(address: S) -> Self {
+        Self::Socks5 {
+            address: address.into(),
+            auth: None,
+        }
+    }
+
+    /// Create an HTTP proxy without authentication.
+    /// * `address` - The address of the proxy, e.g. `127.0.0.1:8080`
+    pub fn http<S: Into<String>>(address: S) -> Self {
+        Self::Http {
+            address: address.into(),
+            auth: None,
+        }
+    }
+
+    /// Set username and password.
+    pub fn auth<S: Into<String>>(mut self, username: S, password: S) -> Self {
+        match &mut self {
+            Self::Socks5 { auth, .. } | Self::Http { auth, .. } => {
+                *auth = Some((username.into(), password.into()))
+            }
+        }
+        self
+    }
+
+    /// Returns the address of the proxy.
+    pub fn address(&self) -> &str {
+        match self {
+            Self::Socks5 { address, .. } | Self::Http { address, .. } => address,
+        }
+    }
+
+    /// Connect to the target through the proxy.
+    /// A host name is resolved by the proxy, not locally.
+    /// * `target` - The host and port of the target, e.g. `example.com:443` or `[::1]:443`
+    pub async fn connect(&self, target: &str) -> Result<TcpStream, ClientError> {
+        let (host, port) = split_host_port(target)?;
+        let mut stream = TcpStream::connect(self.address()).await?;
+        match self {
+            Self::Socks5 { auth, .. } => socks5_connect(&mut stream, host, port, auth).await?,
+            Self::Http { auth, .. } => http_connect(&mut stream, host, port, auth).await?,
+        }
+        Ok(stream)
+    }
+}
+
+/// Split `host:port` into the host without brackets and the port.
+fn split_host_port(target: &str) -> Result<(&str, u16), ClientError> {
+    target
+        .rsplit_once(':')
+        .and_then(|(host, port)| {
+            let host = host.trim_start_matches('[').trim_end_matches(']');
+            Some((host, port.parse().ok()?)).filter(|_| !host.is_empty())
+        })
+        .ok_or_else(|| {
+            ClientError::TokioError(std::io::Error::new(
+                std::io::ErrorKind::InvalidInput,
+                "The target is not in the form of host:port.",
+            ))
+        })
+}
+
+/// Establish a SOCKS5 tunnel.
+async fn socks5_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    auth: &Option<(String, String)>,
+) -> Result<(), ClientError> {
+    match auth {
+        Some(_) => stream.write_all(&[0x05, 0x02, 0x00, 0x02]).await?,
+        None => stream.write_all(&[0x05, 0x01, 0x00]).await?,
+    }
+    let mut reply = [0u8; 2];
+    stream.read_exact(&mut reply).await?;
+    match (reply, auth) {
+        ([0x05, 0x00], _) => {}
+        ([0x05, 0x02], Some((username, password))) => {
+            if username.len() > 255 || password.len() > 255 {
+                return Err(ClientError::ProxyHandshakeFailed);
+            }
+            let mut request = vec![0x01, username.len() as u8];
+            request.extend_from_slice(username.as_bytes());
+            request.push(password.len() as u8);
+            request.extend_from_slice(password.as_bytes());
+            stream.write_all(&request).await?;
+            stream.read_exact(&mut reply).await?;
+            if reply[1] != 0x00 {
+                return Err(ClientError::ProxyHandshakeFailed);
+            }
+        }
+        _ => return Err(ClientError::ProxyHandshakeFailed),
+    }
+    let mut request = vec![0x05, 0x01, 0x00];
+    match host.parse::<IpAddr>() {
+        Ok(IpAddr::V4(v)) => {
+            request.push(0x01);
+            request.extend_from_slice(&v.octets());
+        }
+        Ok(IpAddr::V6(v)) => {
+            request.push(0x04);
+            request.extend_from_slice(&v.octets());
+        }
+        Err(_) => {
+            if host.len() > 255 {
+                return Err(ClientError::ProxyHandshakeFailed);
+            }
+            request.push(0x03);
+            request.push(host.len() as u8);
+            request.extend_from_slice(host.as_bytes());
+        }
+    }
+    request.extend_from_slice(&port.to_be_bytes());
+    stream.write_all(&request).await?;
+    let mut reply = [0u8; 4];
+    stream.read_exact(&mut reply).await?;
+    if reply[0] != 0x05 || reply[1] != 0x00 {
+        return Err(ClientError::ProxyHandshakeFailed);
+    }
+    // Skip the bound address and port.
+    let le = match reply[3] {
+        0x01 => 4,
+        0x03 => stream.read_u8().await? as usize,
+        0x04 => 16,
+        _ => return Err(ClientError::ProxyHandshakeFailed),
+    };
+    let mut bound = vec![0u8; le + 2];
+    stream.read_exact(&mut bound).await?;
+    Ok(())
+}
+
+/// Establish a tunnel with HTTP `CONNECT`.
+async fn http_connect(
+    stream: &mut TcpStream,
+    host: &str,
+    port: u16,
+    auth: &Option<(String, String)>,
+) -> Result<(), ClientError> {
+    let target = if host.contains(':') {
+        format!("[{host}]:{port}")
+    } else {
+        format!("{host}:{port}")
+    };
+    let mut request = format!("CONNECT {target} HTTP/1.1\r\nHost: {target}\r\n");
+    if let Some((username, password)) = auth {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{username}:{password}"));
+        request.push_str(&format!("Proxy-Authorization: Basic {credentials}\r\n"));
+    }
+    request.push_str("\r\n");
+    stream.write_all(request.as_bytes()).await?;
+    // Read byte by byte so no data after the response is consumed.
+    let mut response = Vec::new();
+    while !response.ends_with(b"\r\n\r\n") {
+        if response.len() > 8192 {
+            return Err(ClientError::ProxyHandshakeFailed);
+        }
+        response.push(stream.read_u8().await?);
+    }
+    let status = response
+        .split(|v| *v == b' ')
+        .nth(1)
+        .ok_or(ClientError::ProxyHandshakeFailed)?;
+    if !response.starts_with(b"HTTP/1.") || status != b"200" {
+        return Err(ClientError::ProxyHandshakeFailed);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_socks5() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 4];
+        stream.read_exact(&mut greeting).await.unwrap();
+        assert_eq!(greeting, [0x05, 0x02, 0x00, 0x02]);
+        stream.write_all(&[0x05, 0x02]).await.unwrap();
+        let mut auth = [0u8; 11];
+        stream.read_exact(&mut auth).await.unwrap();
+        assert_eq!(&auth, b"\x01\x04user\x04pass");
+        stream.write_all(&[0x01, 0x00]).await.unwrap();
+        let mut request = [0u8; 10];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(
+            request,
+            [0x05, 0x01, 0x00, 0x01, 149, 154, 167, 50, 0x01, 0xbb]
+        );
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x01, 0, 0, 0, 0, 0, 0])
+            .await
+            .unwrap();
+        stream.write_all(b"hello").await.unwrap();
+    });
+    let mut stream = Proxy::socks5(address.to_string())
+        .auth("user", "pass")
+        .connect("149.154.167.50:443")
+        .await
+        .unwrap();
+    let mut data = [0u8; 5];
+    stream.read_exact(&mut data).await.unwrap();
+    assert_eq!(&data, b"hello");
+    server.await.unwrap();
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_socks5_domain() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut greeting = [0u8; 3];
+        stream.read_exact(&mut greeting).await.unwrap();
+        stream.write_all(&[0x05, 0x00]).await.unwrap();
+        let mut request = [0u8; 18];
+        stream.read_exact(&mut request).await.unwrap();
+        assert_eq!(&request, b"\x05\x01\x00\x03\x0bexample.com\x01\xbb");
+        stream
+            .write_all(&[0x05, 0x00, 0x00, 0x03, 0x01, b'a', 0, 0])
+            .await
+            .unwrap();
+        stream.write_all(b"hello").await.unwrap();
+    });
+    let mut stream = Proxy::socks5(address.to_string())
+        .connect("example.com:443")
+        .await
+        .unwrap();
+    let mut data = [0u8; 5];
+    stream.read_exact(&mut data).await.unwrap();
+    assert_eq!(&data, b"hello");
+    server.await.unwrap();
+}
+
+#[test]
+fn test_split_host_port() {
+    assert_eq!(
+        split_host_port("example.com:443").unwrap(),
+        ("example.com", 443)
+    );
+    assert_eq!(split_host_port("[::1]:80").unwrap(), ("::1", 80));
+    assert!(split_host_port("example.com").is_err());
+    assert!(split_host_port(":443").is_err());
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_http_connect() {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        assert_eq!(
+            String::from_utf8(request).unwrap(),
+            "CONNECT 149.154.167.50:443 HTTP/1.1\r\nHost: 149.154.167.50:443\r\n\
+             Proxy-Authorization: Basic dXNlcjpwYXNz\r\n\r\n"
+        );
+        stream
+            .write_all(b"HTTP/1.1 200 Connection established\r\n\r\nhello")
+            .await
+            .unwrap();
+    });
+    let mut stream = Proxy::http(address.to_string())
+        .auth("user", "pass")
+        .connect("149.154.167.50:443")
+        .await
+        .unwrap();
+    let mut data = [0u8; 5];
+    stream.read_exact(&mut data).await.unwrap();
+    assert_eq!(&data, b"hello");
+    server.await.unwrap();
+}
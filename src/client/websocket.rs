@@ -0,0 +1,273 @@
+use super::ClientError;
+use base64::Engine;
+use openssl::error::ErrorStack;
+use openssl::sha::sha1;
+use std::str::FromStr;
+
+/// The GUID which is appended to `Sec-WebSocket-Key` to compute `Sec-WebSocket-Accept`.
+const ACCEPT_GUID: &str = "258EAFA5-E914-47DA-95CA-C5AB0DC85B11";
+
+/// Continuation frame
+pub const OPCODE_CONTINUATION: u8 = 0x0;
+/// Binary frame
+pub const OPCODE_BINARY: u8 = 0x2;
+/// Close frame
+pub const OPCODE_CLOSE: u8 = 0x8;
+/// Ping frame
+pub const OPCODE_PING: u8 = 0x9;
+/// Pong frame
+pub const OPCODE_PONG: u8 = 0xa;
+
+/// A WebSocket endpoint which the connection is transported over, e.g.
+/// `wss://venus.web.telegram.org/apiws`.
+/// [More](https://core.telegram.org/mtproto/transports#websocket)
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct WebSocket {
+    /// Whether the connection is wrapped in TLS (`wss`)
+    secure: bool,
+    /// The host name
+    host: String,
+    /// The port
+    port: u16,
+    /// The path of the request
+    path: String,
+}
+
+impl WebSocket {
+    /// Parse a `ws://` or `wss://` URL. The path is `/apiws` if it is omitted.
+    pub fn parse(url: &str) -> Result<Self, ClientError> {
+        let (secure, rest) = if let Some(rest) = url.strip_prefix("wss://") {
+            (true, rest)
+        } else if let Some(rest) = url.strip_prefix("ws://") {
+            (false, rest)
+        } else {
+            return Err(ClientError::InvalidWebSocketUrl);
+        };
+        let (authority, path) = match rest.find('/') {
+            Some(i) => (&rest[..i], &rest[i..]),
+            None => (rest, "/apiws"),
+        };
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) if !port.contains(']') => (
+                host,
+                port.parse().map_err(|_| ClientError::InvalidWebSocketUrl)?,
+            ),
+            _ => (authority, if secure { 443 } else { 80 }),
+        };
+        if host.is_empty() {
+            return Err(ClientError::InvalidWebSocketUrl);
+        }
+        Ok(Self {
+            secure,
+            host: host.to_string(),
+            port,
+            path: path.to_string(),
+        })
+    }
+
+    /// Returns true if the connection is wrapped in TLS.
+    pub fn is_secure(&self) -> bool {
+        self.secure
+    }
+
+    /// Returns the host name.
+    pub fn host(&self) -> &str {
+        &self.host
+    }
+
+    /// Returns the address to connect to, e.g. `venus.web.telegram.org:443`.
+    pub fn address(&self) -> String {
+        format!("{}:{}", self.host, self.port)
+    }
+
+    /// Returns the path of the request.
+    pub fn path(&self) -> &str {
+        &self.path
+    }
+}
+
+impl FromStr for WebSocket {
+    type Err = ClientError;
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Self::parse(s)
+    }
+}
+
+/// Generate the opening handshake request.
+/// Returns `(request, key)` where `key` is the value of `Sec-WebSocket-Key`.
+pub fn handshake_request(ws: &WebSocket) -> Result<(Vec<u8>, String), ErrorStack> {
+    let mut nonce = [0u8; 16];
+    openssl::rand::rand_bytes(&mut nonce)?;
+    let key = base64::engine::general_purpose::STANDARD.encode(nonce);
+    let host = if ws.port == if ws.secure { 443 } else { 80 } {
+        ws.host.clone()
+    } else {
+        ws.address()
+    };
+    let request = format!(
+        "GET {} HTTP/1.1\r\nHost: {}\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+         Sec-WebSocket-Key: {}\r\nSec-WebSocket-Version: 13\r\nSec-WebSocket-Protocol: binary\r\n\r\n",
+        ws.path, host, key
+    );
+    Ok((request.into_bytes(), key))
+}
+
+/// Verify the response of the opening handshake.
+/// * `response` - The status line and headers
+/// * `key` - The value of `Sec-WebSocket-Key` in the request
+pub fn verify_handshake_response(response: &[u8], key: &str) -> bool {
+    let response = String::from_utf8_lossy(response);
+    let mut lines = response.split("\r\n");
+    if lines.next().and_then(|v| v.split(' ').nth(1)) != Some("101") {
+        return false;
+    }
+    let accept = base64::engine::general_purpose::STANDARD
+        .encode(sha1(format!("{key}{ACCEPT_GUID}").as_bytes()));
+    lines
+        .filter_map(|v| v.split_once(':'))
+        .any(|(k, v)| k.trim().eq_ignore_ascii_case("sec-websocket-accept") && v.trim() == accept)
+}
+
+/// Encode a frame which is sent by client. Client frames are always masked.
+pub fn encode_frame(opcode: u8, data: &[u8]) -> Result<Vec<u8>, ErrorStack> {
+    let mut frame = Vec::with_capacity(data.len() + 14);
+    frame.push(0x80 | opcode);
+    match data.len() {
+        le if le < 126 => frame.push(0x80 | le as u8),
+        le if le <= u16::MAX as usize => {
+            frame.push(0x80 | 126);
+            frame.extend_from_slice(&(le as u16).to_be_bytes());
+        }
+        le => {
+            frame.push(0x80 | 127);
+            frame.extend_from_slice(&(le as u64).to_be_bytes());
+        }
+    }
+    let mut mask = [0u8; 4];
+    openssl::rand::rand_bytes(&mut mask)?;
+    frame.extend_from_slice(&mask);
+    frame.extend(data.iter().enumerate().map(|(i, v)| v ^ mask[i % 4]));
+    Ok(frame)
+}
+
+#[test]
+fn test_parse_websocket() {
+    let ws = WebSocket::parse("wss://venus.web.telegram.org/apiws").unwrap();
+    assert!(ws.is_secure());
+    assert_eq!(ws.address(), "venus.web.telegram.org:443");
+    assert_eq!(ws.path(), "/apiws");
+    let ws: WebSocket = "ws://127.0.0.1:8080".parse().unwrap();
+    assert!(!ws.is_secure());
+    assert_eq!(ws.address(), "127.0.0.1:8080");
+    assert_eq!(ws.path(), "/apiws");
+    assert!(WebSocket::parse("http://example.com/apiws").is_err());
+    assert!(WebSocket::parse("ws://example.com:x/apiws").is_err());
+}
+
+#[test]
+fn test_verify_handshake_response() {
+    // The example in RFC 6455
+    let key = "dGhlIHNhbXBsZSBub25jZQ==";
+    assert!(verify_handshake_response(
+        b"HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\nConnection: Upgrade\r\n\
+          Sec-WebSocket-Accept: s3pPLMBiTxaQ9kYGzzhZRbK+xOo=\r\n\r\n",
+        key
+    ));
+    assert!(!verify_handshake_response(
+        b"HTTP/1.1 101 Switching Protocols\r\nSec-WebSocket-Accept: bad\r\n\r\n",
+        key
+    ));
+    assert!(!verify_handshake_response(
+        b"HTTP/1.1 400 Bad Request\r\n\r\n",
+        key
+    ));
+}
+
+#[cfg(test)]
+#[tokio::test]
+async fn test_websocket() {
+    use super::obfuscation::AesCtr;
+    use super::{ClientBuilder, TransportType};
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+
+    /// Read a masked frame and returns `(opcode, payload)`.
+    async fn read_frame(stream: &mut tokio::net::TcpStream) -> (u8, Vec<u8>) {
+        let mut header = [0u8; 2];
+        stream.read_exact(&mut header).await.unwrap();
+        assert_eq!(header[0] & 0x80, 0x80);
+        assert_eq!(header[1] & 0x80, 0x80);
+        let le = (header[1] & 0x7f) as usize;
+        assert!(le < 126);
+        let mut mask = [0u8; 4];
+        stream.read_exact(&mut mask).await.unwrap();
+        let mut payload = vec![0u8; le];
+        stream.read_exact(&mut payload).await.unwrap();
+        for (i, v) in payload.iter_mut().enumerate() {
+            *v ^= mask[i % 4];
+        }
+        (header[0] & 0x0f, payload)
+    }
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let address = listener.local_addr().unwrap();
+    let server = tokio::spawn(async move {
+        let (mut stream, _) = listener.accept().await.unwrap();
+        let mut request = Vec::new();
+        while !request.ends_with(b"\r\n\r\n") {
+            request.push(stream.read_u8().await.unwrap());
+        }
+        let request = String::from_utf8(request).unwrap();
+        assert!(request.starts_with(&format!("GET /apiws HTTP/1.1\r\nHost: {address}\r\n")));
+        let key = request
+            .split("\r\n")
+            .find_map(|v| v.strip_prefix("Sec-WebSocket-Key: "))
+            .unwrap();
+        let accept = base64::engine::general_purpose::STANDARD
+            .encode(sha1(format!("{key}{ACCEPT_GUID}").as_bytes()));
+        stream
+            .write_all(
+                format!(
+                    "HTTP/1.1 101 Switching Protocols\r\nUpgrade: websocket\r\n\
+                     Connection: Upgrade\r\nSec-WebSocket-Accept: {accept}\r\n\r\n"
+                )
+                .as_bytes(),
+            )
+            .await
+            .unwrap();
+        let (opcode, init) = read_frame(&mut stream).await;
+        assert_eq!(opcode, OPCODE_BINARY);
+        assert_eq!(init.len(), 64);
+        let reversed = init[8..56].iter().rev().copied().collect::<Vec<_>>();
+        let mut decryptor = AesCtr::new(&init[8..40], &init[40..56]).unwrap();
+        let mut encryptor = AesCtr::new(&reversed[0..32], &reversed[32..48]).unwrap();
+        let mut decrypted = init.clone();
+        decryptor.apply(&mut decrypted).unwrap();
+        assert_eq!(decrypted[56..60], [0xef; 4]);
+        let (opcode, mut packet) = read_frame(&mut stream).await;
+        assert_eq!(opcode, OPCODE_BINARY);
+        decryptor.apply(&mut packet).unwrap();
+        assert_eq!(&packet, b"\x0201234567");
+        // The ping is answered while the client waits for data.
+        stream.write_all(&[0x89, 0x02, b'h', b'i']).await.unwrap();
+        assert_eq!(read_frame(&mut stream).await, (OPCODE_PONG, b"hi".to_vec()));
+        let mut answer = b"\x0276543210".to_vec();
+        encryptor.apply(&mut answer).unwrap();
+        let mut frame = vec![0x82, answer.len() as u8];
+        frame.extend_from_slice(&answer);
+        stream.write_all(&frame).await.unwrap();
+        stream.write_all(&[0x88, 0x00]).await.unwrap();
+    });
+    let client = ClientBuilder::new()
+        .transport_type(TransportType::Abridged)
+        .websocket(WebSocket::parse(&format!("ws://{address}")).unwrap())
+        .build("0.0.0.0:0")
+        .await
+        .unwrap();
+    client.send(b"01234567".to_vec()).await.unwrap();
+    assert_eq!(&client.recv().await.unwrap()[..], b"76543210");
+    assert!(matches!(
+        client.recv().await,
+        Err(ClientError::Disconnected)
+    ));
+    server.await.unwrap();
+}
@@ -59,8 +59,9 @@ where
     type Error = DeserializeError;
     fn deserialize<R: Read>(data: &mut R) -> Result<Self, Self::Error> {
         let le = u32::deserialize(data)?;
-        let mut v = Vec::with_capacity(le as usize);
-        for _ in 0..le - 1 {
+        // The length comes from the peer, so it is not trusted for preallocation.
+        let mut v = Vec::with_capacity((le as usize).min(1024));
+        for _ in 0..le {
             v.push(T::deserialize(data)?);
         }
         Ok(v)
@@ -195,9 +196,13 @@ impl Deserialize for BytesMut {
             let le = u32::from_le_bytes(le2);
             (le, 3 - ((le - 1) % 4))
         };
-        let mut s = BytesMut::with_capacity(le as usize);
-        s.resize(le as usize, 0);
-        data.read_exact(&mut s)?;
+        let mut s = Vec::new();
+        if data.take(le as u64).read_to_end(&mut s)? != le as usize {
+            return Err(DeserializeError::from(
+                "The string is longer than the data.",
+            ));
+        }
+        let s = BytesMut::from(s.as_slice());
         if pd > 0 {
             let mut pdb = BytesMut::with_capacity(pd as usize);
             pdb.resize(pd as usize, 0);
@@ -370,9 +375,16 @@ impl Deserialize for UnencryptedMessage {
         let auth_key_id = i64::deserialize(data)?;
         let message_id = i64::deserialize(data)?;
         let message_len = u32::deserialize(data)?;
-        let mut payload = BytesMut::with_capacity(message_len as usize);
-        payload.resize(message_len as usize, 0);
-        data.read_exact(&mut payload)?;
+        if message_len % 4 != 0 {
+            return Err("message_data_length is not a multiple of 4.".into());
+        }
+        // Read without allocating `message_len` bytes first, since the length is not trusted.
+        let mut payload = Vec::new();
+        data.take(message_len as u64).read_to_end(&mut payload)?;
+        if payload.len() != message_len as usize {
+            return Err("message_data_length is longer than the message.".into());
+        }
+        let payload = BytesMut::from(&payload[..]);
         Ok(Self {
             auth_key_id,
             message_id,
@@ -381,6 +393,106 @@ impl Deserialize for UnencryptedMessage {
     }
 }
 
+/// Encrypted message
+#[derive(Clone, Debug)]
+pub struct EncryptedMessage {
+    /// The identifier of the authorization key
+    pub auth_key_id: i64,
+    /// Message key
+    pub msg_key: [u8; 16],
+    /// Encrypted [DecryptedMessage] with padding
+    pub encrypted_data: BytesMut,
+}
+
+impl Serialize for EncryptedMessage {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.auth_key_id.serialize(writer)?;
+        writer.write_all(&self.msg_key)?;
+        writer.write_all(&self.encrypted_data)
+    }
+}
+
+impl Deserialize for EncryptedMessage {
+    type Error = DeserializeError;
+    fn deserialize<R: Read>(data: &mut R) -> Result<Self, Self::Error> {
+        let auth_key_id = i64::deserialize(data)?;
+        let mut msg_key = [0u8; 16];
+        data.read_exact(&mut msg_key)?;
+        let mut encrypted_data = Vec::new();
+        data.read_to_end(&mut encrypted_data)?;
+        Ok(Self {
+            auth_key_id,
+            msg_key,
+            encrypted_data: BytesMut::from(encrypted_data.as_slice()),
+        })
+    }
+}
+
+/// Decrypted message (without padding)
+#[derive(Clone, Debug)]
+pub struct DecryptedMessage {
+    /// Server salt
+    pub salt: i64,
+    /// Session id
+    pub session_id: i64,
+    /// Message id
+    pub message_id: i64,
+    /// Message sequence number
+    pub seq_no: i32,
+    /// Payload
+    pub payload: BytesMut,
+}
+
+impl DecryptedMessage {
+    /// Deserialize the message payload
+    pub fn deserialize_payload<T: Deserialize>(&self) -> Result<T, T::Error> {
+        T::deserialize_from_bytes(&self.payload)
+    }
+    /// Deserialize the message payload
+    pub fn opt_deserialize_payload<T: OptDeserialize>(&self) -> Result<Option<T>, T::Error> {
+        T::opt_deserialize_from_bytes(&self.payload)
+    }
+}
+
+impl Serialize for DecryptedMessage {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.salt.serialize(writer)?;
+        self.session_id.serialize(writer)?;
+        self.message_id.serialize(writer)?;
+        self.seq_no.serialize(writer)?;
+        (self.payload.len() as i32).serialize(writer)?;
+        writer.write_all(&self.payload)
+    }
+}
+
+impl Deserialize for DecryptedMessage {
+    type Error = DeserializeError;
+    fn deserialize<R: Read>(data: &mut R) -> Result<Self, Self::Error> {
+        let salt = i64::deserialize(data)?;
+        let session_id = i64::deserialize(data)?;
+        let message_id = i64::deserialize(data)?;
+        let seq_no = i32::deserialize(data)?;
+        let message_len = u32::deserialize(data)?;
+        if message_len % 4 != 0 {
+            return Err("message_data_length is not a multiple of 4.".into());
+        }
+        // Read without allocating `message_len` bytes first, since the length is not trusted.
+        let mut payload = Vec::new();
+        data.take(message_len as u64).read_to_end(&mut payload)?;
+        if payload.len() != message_len as usize {
+            return Err("message_data_length is longer than the message.".into());
+        }
+        let payload = BytesMut::from(&payload[..]);
+        Ok(Self {
+            salt,
+            session_id,
+            message_id,
+            seq_no,
+            payload,
+        })
+    }
+}
+
 #[test]
 fn test_type_id() {
     assert_eq!(vec![1].type_id(), 0x1cb5c415);
@@ -467,4 +579,35 @@ fn test_deserialize() {
         I256::deserialize_from_bytes(&i.serialize_to_bytes()).unwrap(),
         i
     );
+    let v = Box::new(vec![1i64, 2, 3]);
+    assert_eq!(
+        Box::<Vec<i64>>::deserialize_from_bytes(&v.serialize_to_bytes()).unwrap(),
+        v
+    );
+    let v: Box<Vec<i64>> = Box::default();
+    assert_eq!(
+        Box::<Vec<i64>>::deserialize_from_bytes(&v.serialize_to_bytes()).unwrap(),
+        v
+    );
+}
+
+#[test]
+fn test_deserialize_decrypted_message() {
+    let message = DecryptedMessage {
+        salt: 1,
+        session_id: 2,
+        message_id: 3,
+        seq_no: 4,
+        payload: BytesMut::from(&[5u8; 8][..]),
+    };
+    let mut data = message.serialize_to_bytes();
+    data.extend_from_slice(&[0u8; 12]);
+    let decoded = DecryptedMessage::deserialize_from_bytes(&data).unwrap();
+    assert_eq!(decoded.payload, message.payload);
+    // message_data_length is longer than the plaintext.
+    data[28..32].copy_from_slice(&1024u32.to_le_bytes());
+    assert!(DecryptedMessage::deserialize_from_bytes(&data).is_err());
+    // message_data_length is not a multiple of 4.
+    data[28..32].copy_from_slice(&6u32.to_le_bytes());
+    assert!(DecryptedMessage::deserialize_from_bytes(&data).is_err());
 }
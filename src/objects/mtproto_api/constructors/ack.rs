@@ -0,0 +1,16 @@
+use crate::objects::traits::TypeId;
+
+/// Acknowledgment of receipt. [More](https://core.telegram.org/mtproto/service_messages_about_messages#acknowledgment-of-receipt)
+///
+/// Origin: `msgs_ack#62d6b459 msg_ids:Vector<long> = MsgsAck;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct msgs_ack {
+    /// The identifiers of messages received.
+    pub msg_ids: Box<Vec<i64>>,
+}
+
+impl TypeId for msgs_ack {
+    fn type_id2() -> u32 {
+        0x62d6b459
+    }
+}
@@ -0,0 +1,91 @@
+use crate::objects::traits::TypeId;
+
+/// The reason why a message is ignored by server.
+/// [More](https://core.telegram.org/mtproto/service_messages_about_messages#notice-of-ignored-error-message)
+#[derive(Clone, Copy, Debug, PartialEq, Eq, derive_more::Display)]
+pub enum BadMsgCode {
+    /// `16`: msg_id too low. The client time has to be synchronized.
+    MsgIdTooLow,
+    /// `17`: msg_id too high. The client time has to be synchronized.
+    MsgIdTooHigh,
+    /// `18`: Incorrect two lower order msg_id bits. The server expects client message msg_id to be divisible by 4.
+    MsgIdInvalid,
+    /// `19`: Container msg_id is the same as msg_id of a previously received message.
+    ContainerMsgIdDuplicated,
+    /// `20`: Message too old, and it cannot be verified whether the server has received a message with this msg_id or not.
+    MsgTooOld,
+    /// `32`: msg_seqno too low. The server has already received a message with a lower msg_id but with either a higher or an equal and odd seqno.
+    SeqNoTooLow,
+    /// `33`: msg_seqno too high. There is a message with a higher msg_id but with either a lower or an equal and odd seqno.
+    SeqNoTooHigh,
+    /// `34`: An even msg_seqno expected (irrelevant message), but odd received.
+    EvenSeqNoExpected,
+    /// `35`: Odd msg_seqno expected (relevant message), but even received.
+    OddSeqNoExpected,
+    /// `48`: Incorrect server salt. The new salt is in [bad_server_salt].
+    BadServerSalt,
+    /// `64`: Invalid container.
+    InvalidContainer,
+    /// Unknown error code
+    #[display(fmt = "Unknown({})", _0)]
+    Unknown(i32),
+}
+
+impl From<i32> for BadMsgCode {
+    fn from(code: i32) -> Self {
+        match code {
+            16 => Self::MsgIdTooLow,
+            17 => Self::MsgIdTooHigh,
+            18 => Self::MsgIdInvalid,
+            19 => Self::ContainerMsgIdDuplicated,
+            20 => Self::MsgTooOld,
+            32 => Self::SeqNoTooLow,
+            33 => Self::SeqNoTooHigh,
+            34 => Self::EvenSeqNoExpected,
+            35 => Self::OddSeqNoExpected,
+            48 => Self::BadServerSalt,
+            64 => Self::InvalidContainer,
+            v => Self::Unknown(v),
+        }
+    }
+}
+
+/// Server notify that an incoming message is ignored. [More](https://core.telegram.org/mtproto/service_messages_about_messages#notice-of-ignored-error-message)
+///
+/// Origin: `bad_msg_notification#a7eff811 bad_msg_id:long bad_msg_seqno:int error_code:int = BadMsgNotification;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct bad_msg_notification {
+    /// The message id of the ignored message
+    pub bad_msg_id: i64,
+    /// The sequence number of the ignored message
+    pub bad_msg_seqno: i32,
+    /// Error code
+    pub error_code: i32,
+}
+
+impl TypeId for bad_msg_notification {
+    fn type_id2() -> u32 {
+        0xa7eff811
+    }
+}
+
+/// Server notify that an incoming message is ignored because of the incorrect server salt. [More](https://core.telegram.org/mtproto/service_messages_about_messages#notice-of-ignored-error-message)
+///
+/// Origin: `bad_server_salt#edab447b bad_msg_id:long bad_msg_seqno:int error_code:int new_server_salt:long = BadMsgNotification;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct bad_server_salt {
+    /// The message id of the ignored message
+    pub bad_msg_id: i64,
+    /// The sequence number of the ignored message
+    pub bad_msg_seqno: i32,
+    /// Error code. Always `48`.
+    pub error_code: i32,
+    /// The server salt which should be used from now on.
+    pub new_server_salt: i64,
+}
+
+impl TypeId for bad_server_salt {
+    fn type_id2() -> u32 {
+        0xedab447b
+    }
+}
@@ -1,4 +1,5 @@
 use super::super::types::Server_DH_Inner_Data;
+use super::{server_DH_inner_data, CheckDhPrimeError};
 use crate::objects::traits::TypeId;
 use bytes::BytesMut;
 use openssl::{
@@ -14,7 +15,7 @@ pub struct client_DH_inner_data {
     /// Value received from server in [Step 2](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation).
     pub server_nonce: i128,
     /// Equal to zero at the time of the first attempt;
-    /// otherwise, it is equal to auth_key_aux_hash from the previous failed attempt (see [Item 9](https://core.telegram.org/mtproto/auth_key#dh-key-exchange-complete)).
+    ///   otherwise, it is equal to auth_key_aux_hash from the previous failed attempt (see [Item 9](https://core.telegram.org/mtproto/auth_key#dh-key-exchange-complete)).
     pub retry_id: i64,
     /// `pow(g, b) mod dh_prime` (See [Step 6](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication))
     pub g_b: BytesMut,
@@ -28,7 +29,7 @@ impl client_DH_inner_data {
     /// Create a new instance.
     /// * `server_DH_inner_data` - Server's DH inner data received in [Step 5](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication)
     /// * `retry_id` - Equal to zero at the time of the first attempt;
-    /// otherwise, it is equal to auth_key_aux_hash from the previous failed attempt (see [Item 9](https://core.telegram.org/mtproto/auth_key#dh-key-exchange-complete))
+    ///   otherwise, it is equal to auth_key_aux_hash from the previous failed attempt (see [Item 9](https://core.telegram.org/mtproto/auth_key#dh-key-exchange-complete))
     pub fn new(
         server_inner_data: &Server_DH_Inner_Data,
         retry_id: i64,
@@ -62,8 +63,92 @@ impl client_DH_inner_data {
     }
 }
 
+impl client_DH_inner_data {
+    /// Calculate the authorization key: `pow(g_a, b) mod dh_prime`. Returns 256 bytes in big endian format.
+    /// * `server_DH_inner_data` - Server's DH inner data which used to create this instance.
+    pub fn auth_key(
+        &self,
+        server_inner_data: &server_DH_inner_data,
+    ) -> Result<BytesMut, openssl::error::ErrorStack> {
+        let g_a = BigNum::from_slice(&server_inner_data.g_a)?;
+        let b = BigNum::from_slice(&self.b)?;
+        let prime = BigNum::from_slice(&server_inner_data.dh_prime)?;
+        let mut key = BigNum::new()?;
+        let mut ctx = BigNumContext::new()?;
+        key.mod_exp(&g_a, &b, &prime, &mut ctx)?;
+        Ok(BytesMut::from(key.to_vec_padded(256)?.as_slice()))
+    }
+
+    /// Check `g_b`. It should be in range `1 < g_b < dh_prime - 1`.
+    /// * `server_DH_inner_data` - Server's DH inner data which used to create this instance.
+    pub fn check_g_b(
+        &self,
+        server_inner_data: &server_DH_inner_data,
+    ) -> Result<(), CheckDhPrimeError> {
+        let prime = BigNum::from_slice(&server_inner_data.dh_prime)?;
+        let g_b = BigNum::from_slice(&self.g_b)?;
+        let one = BigNum::from_u32(1)?;
+        let mut upper = BigNum::new()?;
+        upper.checked_sub(&prime, &one)?;
+        if g_b <= one || g_b >= upper {
+            return Err("g_b is out of range.".into());
+        }
+        Ok(())
+    }
+}
+
 impl TypeId for client_DH_inner_data {
     fn type_id2() -> u32 {
         0x6643b654
     }
 }
+
+#[test]
+fn test_auth_key() {
+    let server_inner_data = server_DH_inner_data {
+        nonce: 0,
+        server_nonce: 0,
+        g: 3,
+        dh_prime: BytesMut::from(&[0x01, 0x01][..]),
+        g_a: BytesMut::from(&[0x02][..]),
+        server_time: 0,
+    };
+    let client_inner_data = client_DH_inner_data {
+        nonce: 0,
+        server_nonce: 0,
+        retry_id: 0,
+        g_b: BytesMut::new(),
+        b: BytesMut::from(&[0x10][..]),
+    };
+    let key = client_inner_data.auth_key(&server_inner_data).unwrap();
+    assert_eq!(key.len(), 256);
+    // 2 ^ 16 mod 257 = 1
+    assert_eq!(&key[254..], &[0, 1]);
+}
+
+#[test]
+fn test_check_g_b() {
+    let server_inner_data = server_DH_inner_data {
+        nonce: 0,
+        server_nonce: 0,
+        g: 3,
+        dh_prime: BytesMut::from(&[0x01, 0x01][..]),
+        g_a: BytesMut::from(&[0x02][..]),
+        server_time: 0,
+    };
+    let mut client_inner_data = client_DH_inner_data {
+        nonce: 0,
+        server_nonce: 0,
+        retry_id: 0,
+        g_b: BytesMut::from(&[0x02][..]),
+        b: BytesMut::new(),
+    };
+    assert!(client_inner_data.check_g_b(&server_inner_data).is_ok());
+    client_inner_data.g_b = BytesMut::from(&[0x01][..]);
+    assert!(client_inner_data.check_g_b(&server_inner_data).is_err());
+    // dh_prime - 1 = 256
+    client_inner_data.g_b = BytesMut::from(&[0x01, 0x00][..]);
+    assert!(client_inner_data.check_g_b(&server_inner_data).is_err());
+    client_inner_data.g_b = BytesMut::from(&[0xff][..]);
+    assert!(client_inner_data.check_g_b(&server_inner_data).is_ok());
+}
@@ -0,0 +1,41 @@
+use crate::objects::traits::TypeId;
+
+/// Extended voluntary communication of status of one message. [More](https://core.telegram.org/mtproto/service_messages_about_messages#extended-voluntary-communication-of-status-of-one-message)
+///
+/// Origin: `msg_detailed_info#276d3ec6 msg_id:long answer_msg_id:long bytes:int status:int = MsgDetailedInfo;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct msg_detailed_info {
+    /// The message id of the request
+    pub msg_id: i64,
+    /// The message id of the answer
+    pub answer_msg_id: i64,
+    /// The size of the answer
+    pub bytes: i32,
+    /// Always `0`
+    pub status: i32,
+}
+
+impl TypeId for msg_detailed_info {
+    fn type_id2() -> u32 {
+        0x276d3ec6
+    }
+}
+
+/// Extended voluntary communication of status of one message which is not a response. [More](https://core.telegram.org/mtproto/service_messages_about_messages#extended-voluntary-communication-of-status-of-one-message)
+///
+/// Origin: `msg_new_detailed_info#809db6df answer_msg_id:long bytes:int status:int = MsgDetailedInfo;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct msg_new_detailed_info {
+    /// The message id of the message
+    pub answer_msg_id: i64,
+    /// The size of the message
+    pub bytes: i32,
+    /// Always `0`
+    pub status: i32,
+}
+
+impl TypeId for msg_new_detailed_info {
+    fn type_id2() -> u32 {
+        0x809db6df
+    }
+}
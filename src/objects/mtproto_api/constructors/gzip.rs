@@ -0,0 +1,59 @@
+use crate::objects::error::DeserializeError;
+use crate::objects::traits::TypeId;
+use bytes::BytesMut;
+use std::io::Read;
+
+/// The maximum size of a decompressed object.
+const MAX_UNPACKED_LEN: usize = 16 * 1024 * 1024;
+
+/// An object compressed with gzip. [More](https://core.telegram.org/mtproto/service_messages#packed-object)
+///
+/// Origin: `gzip_packed#3072cfa1 packed_data:string = Object;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct gzip_packed {
+    /// Compressed object
+    pub packed_data: BytesMut,
+}
+
+impl gzip_packed {
+    /// Decompress the object.
+    /// Returns an error if the object is larger than 16 MiB.
+    pub fn unpack(&self) -> Result<BytesMut, DeserializeError> {
+        let decoder = flate2::read::GzDecoder::new(&self.packed_data[..]);
+        let mut data = Vec::new();
+        decoder
+            .take(MAX_UNPACKED_LEN as u64 + 1)
+            .read_to_end(&mut data)?;
+        if data.len() > MAX_UNPACKED_LEN {
+            return Err(DeserializeError::from("The unpacked object is too large."));
+        }
+        Ok(BytesMut::from(data.as_slice()))
+    }
+
+    /// Compress an object.
+    /// * `data` - Serialized object including its constructor id.
+    pub fn pack(data: &[u8]) -> std::io::Result<Self> {
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        std::io::Write::write_all(&mut encoder, data)?;
+        Ok(Self {
+            packed_data: BytesMut::from(encoder.finish()?.as_slice()),
+        })
+    }
+}
+
+impl TypeId for gzip_packed {
+    fn type_id2() -> u32 {
+        0x3072cfa1
+    }
+}
+
+#[test]
+fn test_unpack_limit() {
+    let data = vec![0u8; MAX_UNPACKED_LEN];
+    assert_eq!(
+        gzip_packed::pack(&data).unwrap().unpack().unwrap().len(),
+        data.len()
+    );
+    let data = vec![0u8; MAX_UNPACKED_LEN + 1];
+    assert!(gzip_packed::pack(&data).unwrap().unpack().is_err());
+}
@@ -0,0 +1,227 @@
+use super::{gzip_packed, rpc_result};
+use crate::objects::error::DeserializeError;
+use crate::objects::traits::{Deserialize, Serialize, TypeId};
+use bytes::BytesMut;
+use std::io::{Read, Write};
+
+/// The maximum number of containers and `gzip_packed` objects wrapped around a message.
+const MAX_UNPACK_DEPTH: usize = 4;
+
+/// A message in [msg_container]. [More](https://core.telegram.org/mtproto/service_messages#simple-container)
+///
+/// Origin: `message msg_id:long seqno:int bytes:int body:Object = Message;`
+#[derive(Clone, Debug)]
+pub struct message {
+    /// Message id
+    pub msg_id: i64,
+    /// Message sequence number
+    pub seqno: i32,
+    /// Serialized object including its constructor id.
+    pub body: BytesMut,
+}
+
+impl message {
+    /// Returns the constructor id of the body.
+    pub fn body_type_id(&self) -> Option<u32> {
+        if self.body.len() < 4 {
+            None
+        } else {
+            Some(u32::from_le_bytes(self.body[0..4].try_into().unwrap()))
+        }
+    }
+
+    /// Returns true if the message requires an explicit acknowledgment.
+    pub fn is_content_related(&self) -> bool {
+        self.seqno & 1 == 1
+    }
+
+    /// Unpack containers and `gzip_packed` objects recursively.
+    /// The result of `rpc_result` is also unpacked if it is packed.
+    pub fn unpack(self) -> Result<Vec<message>, DeserializeError> {
+        let mut messages = Vec::new();
+        self.unpack_into(&mut messages, 0)?;
+        Ok(messages)
+    }
+
+    fn unpack_into(
+        self,
+        messages: &mut Vec<message>,
+        depth: usize,
+    ) -> Result<(), DeserializeError> {
+        match self.body_type_id() {
+            Some(id) if id == msg_container::type_id2() || id == gzip_packed::type_id2() => {
+                if depth >= MAX_UNPACK_DEPTH {
+                    return Err(DeserializeError::from("The message is nested too deeply."));
+                }
+                if id == msg_container::type_id2() {
+                    let container = msg_container::deserialize_from_bytes(&self.body[4..])?;
+                    for message in container.messages {
+                        message.unpack_into(messages, depth + 1)?;
+                    }
+                } else {
+                    let packed = gzip_packed::deserialize_from_bytes(&self.body[4..])?;
+                    Self {
+                        msg_id: self.msg_id,
+                        seqno: self.seqno,
+                        body: packed.unpack()?,
+                    }
+                    .unpack_into(messages, depth + 1)?;
+                }
+            }
+            Some(id) if id == rpc_result::type_id2() => {
+                let mut result = rpc_result::deserialize_from_bytes(&self.body[4..])?;
+                if result.result.len() >= 4
+                    && result.result[0..4] == gzip_packed::type_id2().to_le_bytes()
+                {
+                    result.result =
+                        gzip_packed::deserialize_from_bytes(&result.result[4..])?.unpack()?;
+                }
+                messages.push(Self {
+                    msg_id: self.msg_id,
+                    seqno: self.seqno,
+                    body: Box::new(result).serialize_to_bytes(),
+                });
+            }
+            _ => messages.push(self),
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for message {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.msg_id.serialize(writer)?;
+        self.seqno.serialize(writer)?;
+        (self.body.len() as i32).serialize(writer)?;
+        writer.write_all(&self.body)
+    }
+}
+
+impl Deserialize for message {
+    type Error = DeserializeError;
+    fn deserialize<R: Read>(data: &mut R) -> Result<Self, Self::Error> {
+        let msg_id = i64::deserialize(data)?;
+        let seqno = i32::deserialize(data)?;
+        let le = u32::deserialize(data)?;
+        let mut body = Vec::new();
+        if data.take(le as u64).read_to_end(&mut body)? != le as usize {
+            return Err(DeserializeError::from(
+                "The message is longer than the data.",
+            ));
+        }
+        let body = BytesMut::from(body.as_slice());
+        Ok(Self {
+            msg_id,
+            seqno,
+            body,
+        })
+    }
+}
+
+impl From<&crate::objects::base::DecryptedMessage> for message {
+    fn from(v: &crate::objects::base::DecryptedMessage) -> Self {
+        Self {
+            msg_id: v.message_id,
+            seqno: v.seq_no,
+            body: v.payload.clone(),
+        }
+    }
+}
+
+/// A container that holds several messages. [More](https://core.telegram.org/mtproto/service_messages#simple-container)
+///
+/// Origin: `msg_container#73f1f8dc messages:vector<%Message> = MessageContainer;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct msg_container {
+    /// Messages
+    pub messages: Vec<message>,
+}
+
+impl TypeId for msg_container {
+    fn type_id2() -> u32 {
+        0x73f1f8dc
+    }
+}
+
+#[test]
+fn test_unpack() {
+    use super::pong;
+    let pong_body = Box::new(pong {
+        msg_id: 1,
+        ping_id: 2,
+    })
+    .serialize_to_bytes();
+    let result = Box::new(rpc_result {
+        req_msg_id: 3,
+        result: Box::new(gzip_packed::pack(&pong_body).unwrap()).serialize_to_bytes(),
+    })
+    .serialize_to_bytes();
+    let inner = Box::new(msg_container {
+        messages: vec![message {
+            msg_id: 8,
+            seqno: 1,
+            body: result,
+        }],
+    })
+    .serialize_to_bytes();
+    let packed = Box::new(gzip_packed::pack(&inner).unwrap()).serialize_to_bytes();
+    let outer = message {
+        msg_id: 4,
+        seqno: 0,
+        body: Box::new(msg_container {
+            messages: vec![
+                message {
+                    msg_id: 12,
+                    seqno: 2,
+                    body: pong_body.clone(),
+                },
+                message {
+                    msg_id: 16,
+                    seqno: 0,
+                    body: packed,
+                },
+            ],
+        })
+        .serialize_to_bytes(),
+    };
+    let messages = outer.unpack().unwrap();
+    assert_eq!(messages.len(), 2);
+    assert_eq!(messages[0].msg_id, 12);
+    assert_eq!(messages[0].body, pong_body);
+    assert_eq!(messages[1].msg_id, 8);
+    assert!(messages[1].is_content_related());
+    let result = rpc_result::deserialize_from_bytes(&messages[1].body[4..]).unwrap();
+    assert_eq!(result.req_msg_id, 3);
+    assert_eq!(result.result, pong_body);
+}
+
+#[test]
+fn test_unpack_limits() {
+    use super::pong;
+    let pong_body = Box::new(pong {
+        msg_id: 1,
+        ping_id: 2,
+    })
+    .serialize_to_bytes();
+    let mut body = pong_body;
+    for _ in 0..MAX_UNPACK_DEPTH + 1 {
+        body = Box::new(msg_container {
+            messages: vec![message {
+                msg_id: 4,
+                seqno: 0,
+                body,
+            }],
+        })
+        .serialize_to_bytes();
+    }
+    let nested = message {
+        msg_id: 4,
+        seqno: 0,
+        body,
+    };
+    assert!(nested.clone().unpack().is_err());
+
+    let mut truncated = nested.serialize_to_vec();
+    truncated.truncate(truncated.len() - 4);
+    assert!(message::deserialize_from_bytes(&truncated).is_err());
+}
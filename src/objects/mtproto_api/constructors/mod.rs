@@ -1,15 +1,45 @@
+mod ack;
+mod bad_msg;
 mod client_dh_inner_data;
+mod detailed_info;
+mod gzip;
+mod message_container;
+mod msgs_state;
+mod new_session;
 mod p_q_inner_data;
+mod ping;
 mod res_pq;
+mod rpc;
+mod salts;
 mod server_dh_inner_data;
 mod server_dh_params;
+mod set_client_dh_params_answer;
 
+pub use ack::msgs_ack;
+pub use bad_msg::bad_msg_notification;
+pub use bad_msg::bad_server_salt;
+pub use bad_msg::BadMsgCode;
 pub use client_dh_inner_data::client_DH_inner_data;
+pub use detailed_info::msg_detailed_info;
+pub use detailed_info::msg_new_detailed_info;
+pub use gzip::gzip_packed;
+pub use message_container::message;
+pub use message_container::msg_container;
+pub use msgs_state::msgs_state_info;
+pub use new_session::new_session_created;
 pub use p_q_inner_data::p_q_inner_data_dc;
 pub use p_q_inner_data::p_q_inner_data_temp_dc;
+pub use ping::pong;
 pub use res_pq::resPQ;
 pub use res_pq::FactorizeError;
+pub use rpc::rpc_error;
+pub use rpc::rpc_result;
+pub use salts::future_salt;
+pub use salts::future_salts;
 pub use server_dh_inner_data::server_DH_inner_data;
 pub use server_dh_inner_data::CheckDhPrimeError;
 pub use server_dh_params::server_DH_params_ok;
 pub use server_dh_params::DecryptError;
+pub use set_client_dh_params_answer::dh_gen_fail;
+pub use set_client_dh_params_answer::dh_gen_ok;
+pub use set_client_dh_params_answer::dh_gen_retry;
@@ -0,0 +1,19 @@
+use crate::objects::traits::TypeId;
+use bytes::BytesMut;
+
+/// Informational message regarding status of messages. [More](https://core.telegram.org/mtproto/service_messages_about_messages#informational-message-regarding-status-of-messages)
+///
+/// Origin: `msgs_state_info#04deb57d req_msg_id:long info:string = MsgsStateInfo;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct msgs_state_info {
+    /// The message id of `msgs_state_req`
+    pub req_msg_id: i64,
+    /// One byte of status for each message in `msgs_state_req`
+    pub info: BytesMut,
+}
+
+impl TypeId for msgs_state_info {
+    fn type_id2() -> u32 {
+        0x04deb57d
+    }
+}
@@ -0,0 +1,20 @@
+use crate::objects::traits::TypeId;
+
+/// Notify the client that a new session has been created. [More](https://core.telegram.org/mtproto/service_messages#new-session-creation-notification)
+///
+/// Origin: `new_session_created#9ec20908 first_msg_id:long unique_id:long server_salt:long = NewSession;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct new_session_created {
+    /// The identifier of the first message received by server in the new session.
+    pub first_msg_id: i64,
+    /// Generated by the server every time a session is (re-)created.
+    pub unique_id: i64,
+    /// The server salt which should be used from now on.
+    pub server_salt: i64,
+}
+
+impl TypeId for new_session_created {
+    fn type_id2() -> u32 {
+        0x9ec20908
+    }
+}
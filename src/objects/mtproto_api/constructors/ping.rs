@@ -0,0 +1,18 @@
+use crate::objects::traits::TypeId;
+
+/// The response of `ping`. [More](https://core.telegram.org/mtproto/service_messages#ping-messages-pingpong)
+///
+/// Origin: `pong#347773c5 msg_id:long ping_id:long = Pong;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct pong {
+    /// The message id of `ping`
+    pub msg_id: i64,
+    /// `ping_id` in `ping`
+    pub ping_id: i64,
+}
+
+impl TypeId for pong {
+    fn type_id2() -> u32 {
+        0x347773c5
+    }
+}
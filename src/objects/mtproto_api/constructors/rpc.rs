@@ -0,0 +1,64 @@
+use crate::objects::error::DeserializeError;
+use crate::objects::traits::{Deserialize, Serialize, TypeId};
+use bytes::BytesMut;
+use std::io::{Read, Write};
+
+/// The result of a remote procedure call. [More](https://core.telegram.org/mtproto/mtproto-rpc)
+///
+/// Origin: `rpc_result#f35c6d01 req_msg_id:long result:Object = RpcResult;`
+#[derive(Clone, Debug)]
+pub struct rpc_result {
+    /// The message id of the request
+    pub req_msg_id: i64,
+    /// Serialized result including its constructor id.
+    pub result: BytesMut,
+}
+
+impl Serialize for rpc_result {
+    fn serialize<W: Write>(&self, writer: &mut W) -> std::io::Result<()> {
+        self.req_msg_id.serialize(writer)?;
+        writer.write_all(&self.result)
+    }
+}
+
+impl Deserialize for rpc_result {
+    type Error = DeserializeError;
+    fn deserialize<R: Read>(data: &mut R) -> Result<Self, Self::Error> {
+        let req_msg_id = i64::deserialize(data)?;
+        let mut result = Vec::new();
+        data.read_to_end(&mut result)?;
+        Ok(Self {
+            req_msg_id,
+            result: BytesMut::from(result.as_slice()),
+        })
+    }
+}
+
+impl TypeId for rpc_result {
+    fn type_id2() -> u32 {
+        0xf35c6d01
+    }
+}
+
+/// An error occurred during a remote procedure call. [More](https://core.telegram.org/api/errors)
+///
+/// Origin: `rpc_error#2144ca19 error_code:int error_message:string = RpcError;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct rpc_error {
+    /// Error code
+    pub error_code: i32,
+    /// Error message
+    pub error_message: String,
+}
+
+impl std::fmt::Display for rpc_error {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} {}", self.error_code, self.error_message)
+    }
+}
+
+impl TypeId for rpc_error {
+    fn type_id2() -> u32 {
+        0x2144ca19
+    }
+}
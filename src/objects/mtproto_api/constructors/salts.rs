@@ -0,0 +1,39 @@
+use crate::objects::traits::TypeId;
+
+/// A server salt with its validity period. [More](https://core.telegram.org/mtproto/service_messages#request-for-several-future-salts)
+///
+/// Origin: `future_salt#0949d9dc valid_since:int valid_until:int salt:long = FutureSalt;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct future_salt {
+    /// Unix time since which the salt is valid.
+    pub valid_since: i32,
+    /// Unix time until which the salt is valid.
+    pub valid_until: i32,
+    /// Server salt
+    pub salt: i64,
+}
+
+impl TypeId for future_salt {
+    fn type_id2() -> u32 {
+        0x0949d9dc
+    }
+}
+
+/// The response of `get_future_salts`. [More](https://core.telegram.org/mtproto/service_messages#request-for-several-future-salts)
+///
+/// Origin: `future_salts#ae500895 req_msg_id:long now:int salts:vector<future_salt> = FutureSalts;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct future_salts {
+    /// The message id of the request
+    pub req_msg_id: i64,
+    /// Current server time
+    pub now: i32,
+    /// Salts
+    pub salts: Vec<future_salt>,
+}
+
+impl TypeId for future_salts {
+    fn type_id2() -> u32 {
+        0xae500895
+    }
+}
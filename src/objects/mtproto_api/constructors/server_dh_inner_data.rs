@@ -91,6 +91,21 @@ impl server_DH_inner_data {
             }
         }
     }
+
+    /// Check `g_a`. It should be in range `2^{2048-64} < g_a < dh_prime - 2^{2048-64}`.
+    pub fn check_g_a(&self) -> Result<(), CheckDhPrimeError> {
+        let prime = BigNum::from_slice(&self.dh_prime)?;
+        let g_a = BigNum::from_slice(&self.g_a)?;
+        let one = BigNum::from_u32(1)?;
+        let mut lower = BigNum::new()?;
+        lower.lshift(&one, 2048 - 64)?;
+        let mut upper = BigNum::new()?;
+        upper.checked_sub(&prime, &lower)?;
+        if g_a <= lower || g_a >= upper {
+            return Err("g_a is out of range.".into());
+        }
+        Ok(())
+    }
 }
 
 impl TypeId for server_DH_inner_data {
@@ -51,25 +51,8 @@ impl server_DH_params_ok {
             P_Q_inner_data::P_Q_inner_data_dc(v) => &v.new_nonce,
             P_Q_inner_data::P_Q_inner_data_temp_dc(v) => &v.new_nonce,
         };
-        let new_nonce = new_nonce.serialize_to_bytes();
-        let server_nonce = self.server_nonce.serialize_to_bytes();
-        let mut hasher = openssl::sha::Sha1::new();
-        hasher.update(&new_nonce);
-        hasher.update(&server_nonce);
-        let mut tmp_aes_key = BytesMut::with_capacity(32);
-        tmp_aes_key.extend_from_slice(&hasher.finish());
-        let mut hasher = openssl::sha::Sha1::new();
-        hasher.update(&server_nonce);
-        hasher.update(&new_nonce);
-        let hashre = hasher.finish();
-        tmp_aes_key.extend_from_slice(&hashre[0..12]);
-        let mut tmp_aes_iv = BytesMut::with_capacity(32);
-        tmp_aes_iv.extend_from_slice(&hashre[12..20]);
-        let mut hasher = openssl::sha::Sha1::new();
-        hasher.update(&new_nonce);
-        hasher.update(&new_nonce);
-        tmp_aes_iv.extend_from_slice(&hasher.finish());
-        tmp_aes_iv.extend_from_slice(&new_nonce[0..4]);
+        let (tmp_aes_key, mut tmp_aes_iv) =
+            crate::aes::tmp_aes_key_iv(new_nonce, self.server_nonce);
         let mut answer_with_hash = BytesMut::with_capacity(self.encrypted_answer.len());
         answer_with_hash.resize(self.encrypted_answer.len(), 0);
         let aes_key = openssl::aes::AesKey::new_decrypt(&tmp_aes_key)?;
@@ -0,0 +1,52 @@
+use crate::objects::traits::TypeId;
+
+/// Origin: `dh_gen_ok#3bcbf734 nonce:int128 server_nonce:int128 new_nonce_hash1:int128 = Set_client_DH_params_answer;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct dh_gen_ok {
+    /// Value generated by client in [Step 1](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation).
+    pub nonce: i128,
+    /// Value received from server in [Step 2](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation).
+    pub server_nonce: i128,
+    /// 128 lower-order bits of SHA1(new_nonce + 1 + auth_key_aux_hash)
+    pub new_nonce_hash1: i128,
+}
+
+impl TypeId for dh_gen_ok {
+    fn type_id2() -> u32 {
+        0x3bcbf734
+    }
+}
+
+/// Origin: `dh_gen_retry#46dc1fb9 nonce:int128 server_nonce:int128 new_nonce_hash2:int128 = Set_client_DH_params_answer;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct dh_gen_retry {
+    /// Value generated by client in [Step 1](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation).
+    pub nonce: i128,
+    /// Value received from server in [Step 2](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation).
+    pub server_nonce: i128,
+    /// 128 lower-order bits of SHA1(new_nonce + 2 + auth_key_aux_hash)
+    pub new_nonce_hash2: i128,
+}
+
+impl TypeId for dh_gen_retry {
+    fn type_id2() -> u32 {
+        0x46dc1fb9
+    }
+}
+
+/// Origin: `dh_gen_fail#a69dae02 nonce:int128 server_nonce:int128 new_nonce_hash3:int128 = Set_client_DH_params_answer;`
+#[derive(Clone, Debug, tdlib_rs_impl::Deserialize, tdlib_rs_impl::Serialize)]
+pub struct dh_gen_fail {
+    /// Value generated by client in [Step 1](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation).
+    pub nonce: i128,
+    /// Value received from server in [Step 2](https://core.telegram.org/mtproto/auth_key#dh-exchange-initiation).
+    pub server_nonce: i128,
+    /// 128 lower-order bits of SHA1(new_nonce + 3 + auth_key_aux_hash)
+    pub new_nonce_hash3: i128,
+}
+
+impl TypeId for dh_gen_fail {
+    fn type_id2() -> u32 {
+        0xa69dae02
+    }
+}
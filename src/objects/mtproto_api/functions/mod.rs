@@ -1,7 +1,16 @@
-use super::types::P_Q_inner_data;
-use crate::objects::traits::{Serialize, TypeId};
+use super::types::{
+    Client_DH_Inner_Data, P_Q_inner_data, Pong, ResPQ, Server_DH_Params,
+    Set_client_DH_params_answer,
+};
+use crate::objects::traits::{Function, Serialize, TypeId};
 use bytes::BytesMut;
-use openssl::{pkey::Public, rsa::Rsa};
+use openssl::{
+    aes::{aes_ige, AesKey},
+    pkey::Public,
+    rand::rand_bytes,
+    rsa::Rsa,
+    sha::sha1,
+};
 use rand::{Rng, SeedableRng};
 
 #[derive(Clone, Debug, tdlib_rs_impl::Serialize)]
@@ -22,12 +31,22 @@ impl req_pq_multi {
     }
 }
 
+impl Default for req_pq_multi {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl TypeId for req_pq_multi {
     fn type_id2() -> u32 {
         0xbe7e8ef1
     }
 }
 
+impl Function for req_pq_multi {
+    type Return = ResPQ;
+}
+
 #[derive(Clone, Debug, tdlib_rs_impl::Serialize)]
 /// Used to start Diffie-Hellman key exchange. [More](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication)
 ///
@@ -92,6 +111,10 @@ impl TypeId for req_DH_params {
     }
 }
 
+impl Function for req_DH_params {
+    type Return = Server_DH_Params;
+}
+
 #[derive(Clone, Debug, tdlib_rs_impl::Serialize)]
 /// Origin: `set_client_DH_params#f5045f1f nonce:int128 server_nonce:int128 encrypted_data:string = Set_client_DH_params_answer;`
 pub struct set_client_DH_params {
@@ -103,8 +126,155 @@ pub struct set_client_DH_params {
     pub encrypted_data: BytesMut,
 }
 
+impl set_client_DH_params {
+    /// Create a new instance.
+    /// * `client_DH_inner_data` - Inner data generated in [Step 6](https://core.telegram.org/mtproto/auth_key#client-dh-inner-data)
+    /// * `p_q_inner_data` - Data sended in [Step 4](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication)
+    pub fn new(
+        client_dh_inner_data: &Client_DH_Inner_Data,
+        p_q_inner_data: &P_Q_inner_data,
+    ) -> Result<Self, crate::aes::EncryptError> {
+        let (nonce, server_nonce, new_nonce) = match p_q_inner_data {
+            P_Q_inner_data::P_Q_inner_data_dc(v) => (v.nonce, v.server_nonce, &v.new_nonce),
+            P_Q_inner_data::P_Q_inner_data_temp_dc(v) => (v.nonce, v.server_nonce, &v.new_nonce),
+        };
+        let data = client_dh_inner_data.serialize_to_bytes();
+        let mut data_with_hash = BytesMut::with_capacity(data.len() + 36);
+        data_with_hash.extend_from_slice(&sha1(&data));
+        data_with_hash.extend_from_slice(&data);
+        let le = data_with_hash.len();
+        if !le.is_multiple_of(16) {
+            data_with_hash.resize(le + 16 - le % 16, 0);
+            rand_bytes(&mut data_with_hash[le..])?;
+        }
+        let (tmp_aes_key, mut tmp_aes_iv) = crate::aes::tmp_aes_key_iv(new_nonce, server_nonce);
+        let mut encrypted_data = BytesMut::with_capacity(data_with_hash.len());
+        encrypted_data.resize(data_with_hash.len(), 0);
+        let aes_key = AesKey::new_encrypt(&tmp_aes_key)?;
+        aes_ige(
+            &data_with_hash,
+            &mut encrypted_data,
+            &aes_key,
+            &mut tmp_aes_iv,
+            openssl::symm::Mode::Encrypt,
+        );
+        Ok(Self {
+            nonce,
+            server_nonce,
+            encrypted_data,
+        })
+    }
+}
+
 impl TypeId for set_client_DH_params {
     fn type_id2() -> u32 {
         0xf5045f1f
     }
 }
+
+impl Function for set_client_DH_params {
+    type Return = Set_client_DH_params_answer;
+}
+
+#[derive(Clone, Debug, tdlib_rs_impl::Serialize)]
+/// Used to check the connection. [More](https://core.telegram.org/mtproto/service_messages#ping-messages-pingpong)
+///
+/// Origin: `ping#7abe77ec ping_id:long = Pong;`
+pub struct ping {
+    /// Returned in `pong`
+    pub ping_id: i64,
+}
+
+impl TypeId for ping {
+    fn type_id2() -> u32 {
+        0x7abe77ec
+    }
+}
+
+impl Function for ping {
+    type Return = Pong;
+}
+
+#[derive(Clone, Debug, tdlib_rs_impl::Serialize)]
+/// Works like `ping`. In addition, the server closes the connection `disconnect_delay` seconds
+/// after this message is received unless a new ping is received.
+/// [More](https://core.telegram.org/mtproto/service_messages#deferred-connection-closure-pingpong)
+///
+/// Origin: `ping_delay_disconnect#f3427b8c ping_id:long disconnect_delay:int = Pong;`
+pub struct ping_delay_disconnect {
+    /// Returned in `pong`
+    pub ping_id: i64,
+    /// Seconds before the connection is closed by server
+    pub disconnect_delay: i32,
+}
+
+impl TypeId for ping_delay_disconnect {
+    fn type_id2() -> u32 {
+        0xf3427b8c
+    }
+}
+
+impl Function for ping_delay_disconnect {
+    type Return = Pong;
+}
+
+#[derive(Clone, Debug, tdlib_rs_impl::Serialize)]
+/// Keeps an HTTP request open until the server has messages to send or `max_wait` expires.
+/// The server does not answer it with an `rpc_result`.
+/// [More](https://core.telegram.org/mtproto/service_messages#long-poll-for-http-transport)
+///
+/// Origin: `http_wait#9299359f max_delay:int wait_after:int max_wait:int = HttpWait;`
+pub struct http_wait {
+    /// Milliseconds to wait after the first message is ready before responding
+    pub max_delay: i32,
+    /// Milliseconds to wait after the last message is received before responding
+    pub wait_after: i32,
+    /// Milliseconds to wait for a message before responding
+    pub max_wait: i32,
+}
+
+impl TypeId for http_wait {
+    fn type_id2() -> u32 {
+        0x9299359f
+    }
+}
+
+#[test]
+fn test_set_client_dh_params() {
+    use super::constructors::{client_DH_inner_data, p_q_inner_data_dc};
+    use crate::objects::{base::I256, traits::Deserialize};
+    let p_q_inner_data = P_Q_inner_data::from(p_q_inner_data_dc {
+        pq: BytesMut::from(&[0x17, 0xed, 0x48, 0x94, 0x1a, 0x08, 0xf9, 0x81][..]),
+        p: BytesMut::from(&[0x49, 0x4c, 0x55, 0x3b][..]),
+        q: BytesMut::from(&[0x53, 0x91, 0x10, 0x73][..]),
+        nonce: 1,
+        server_nonce: 2,
+        new_nonce: I256::from(3),
+        dc: 2,
+    });
+    let inner = Client_DH_Inner_Data::from(client_DH_inner_data {
+        nonce: 1,
+        server_nonce: 2,
+        retry_id: 0,
+        g_b: BytesMut::from(&[1, 2, 3][..]),
+        b: BytesMut::new(),
+    });
+    let req = set_client_DH_params::new(&inner, &p_q_inner_data).unwrap();
+    assert_eq!(req.nonce, 1);
+    assert_eq!(req.server_nonce, 2);
+    assert_eq!(req.encrypted_data.len() % 16, 0);
+    let (tmp_aes_key, mut tmp_aes_iv) = crate::aes::tmp_aes_key_iv(&I256::from(3), 2);
+    let mut decrypted = vec![0; req.encrypted_data.len()];
+    aes_ige(
+        &req.encrypted_data,
+        &mut decrypted,
+        &AesKey::new_decrypt(&tmp_aes_key).unwrap(),
+        &mut tmp_aes_iv,
+        openssl::symm::Mode::Decrypt,
+    );
+    let data = inner.serialize_to_bytes();
+    assert_eq!(&decrypted[..20], &sha1(&data));
+    let decoded = Client_DH_Inner_Data::deserialize_from_bytes(&decrypted[20..]).unwrap();
+    let Client_DH_Inner_Data::Boxed(decoded) = decoded;
+    assert_eq!(decoded.g_b, BytesMut::from(&[1, 2, 3][..]));
+}
@@ -3,9 +3,11 @@ mod rsa_public_key;
 
 use super::constructors::*;
 use crate::objects::{
-    traits::{Deserialize, TypeId},
+    base::I256,
+    traits::{Deserialize, Serialize, TypeId},
     DeserializeError,
 };
+use openssl::sha::Sha1;
 pub use rsa_public_key::RSAPublicKey;
 use std::ops::Deref;
 
@@ -85,11 +87,20 @@ pub enum Client_DH_Inner_Data {
     Boxed(Box<client_DH_inner_data>),
 }
 
+impl Deref for Client_DH_Inner_Data {
+    type Target = client_DH_inner_data;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
 impl Client_DH_Inner_Data {
     /// Create a new instance.
     /// * `server_DH_inner_data` - Server's DH inner data received in [Step 5](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication)
     /// * `retry_id` - Equal to zero at the time of the first attempt;
-    /// otherwise, it is equal to auth_key_aux_hash from the previous failed attempt (see [Item 9](https://core.telegram.org/mtproto/auth_key#dh-key-exchange-complete))
+    ///   otherwise, it is equal to auth_key_aux_hash from the previous failed attempt (see [Item 9](https://core.telegram.org/mtproto/auth_key#dh-key-exchange-complete))
     pub fn new(
         server_inner_data: &Server_DH_Inner_Data,
         retry_id: i64,
@@ -100,3 +111,276 @@ impl Client_DH_Inner_Data {
         )?)))
     }
 }
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// The response type for function [super::functions::set_client_DH_params]
+pub enum Set_client_DH_params_answer {
+    /// The authorization key is generated successfully.
+    Dh_gen_ok(Box<dh_gen_ok>),
+    /// Should retry with a new `b`.
+    Dh_gen_retry(Box<dh_gen_retry>),
+    /// Failed to generate the authorization key.
+    Dh_gen_fail(Box<dh_gen_fail>),
+}
+
+impl Set_client_DH_params_answer {
+    /// Calculate `new_nonce_hash1`, `new_nonce_hash2` or `new_nonce_hash3`:
+    /// 128 lower-order bits of `SHA1(new_nonce + number + auth_key_aux_hash)`.
+    /// [More](https://core.telegram.org/mtproto/auth_key#dh-key-exchange-complete)
+    pub fn calc_new_nonce_hash(new_nonce: &I256, number: u8, auth_key_aux_hash: i64) -> i128 {
+        let mut hasher = Sha1::new();
+        hasher.update(&new_nonce.serialize_to_bytes());
+        hasher.update(&[number]);
+        hasher.update(&auth_key_aux_hash.to_le_bytes());
+        let hash = hasher.finish();
+        i128::from_le_bytes(hash[4..20].try_into().unwrap())
+    }
+
+    /// Returns `(nonce, server_nonce)`
+    pub fn nonces(&self) -> (i128, i128) {
+        match self {
+            Self::Dh_gen_ok(v) => (v.nonce, v.server_nonce),
+            Self::Dh_gen_retry(v) => (v.nonce, v.server_nonce),
+            Self::Dh_gen_fail(v) => (v.nonce, v.server_nonce),
+        }
+    }
+
+    /// Check whether `new_nonce_hash` in the answer matches the key.
+    /// * `new_nonce` - Client-generated random number in [Step 4](https://core.telegram.org/mtproto/auth_key#presenting-proof-of-work-server-authentication)
+    /// * `auth_key_aux_hash` - The 64 higher-order bits of SHA1 of the authorization key.
+    pub fn check_new_nonce_hash(&self, new_nonce: &I256, auth_key_aux_hash: i64) -> bool {
+        let (number, hash) = match self {
+            Self::Dh_gen_ok(v) => (1, v.new_nonce_hash1),
+            Self::Dh_gen_retry(v) => (2, v.new_nonce_hash2),
+            Self::Dh_gen_fail(v) => (3, v.new_nonce_hash3),
+        };
+        Self::calc_new_nonce_hash(new_nonce, number, auth_key_aux_hash) == hash
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// [New session creation notification](https://core.telegram.org/mtproto/service_messages#new-session-creation-notification)
+pub enum NewSession {
+    Boxed(Box<new_session_created>),
+}
+
+impl Deref for NewSession {
+    type Target = new_session_created;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// [Simple container](https://core.telegram.org/mtproto/service_messages#simple-container)
+pub enum MessageContainer {
+    Boxed(Box<msg_container>),
+}
+
+impl Deref for MessageContainer {
+    type Target = msg_container;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// The result of a remote procedure call. [More](https://core.telegram.org/mtproto/mtproto-rpc)
+pub enum RpcResult {
+    Boxed(Box<rpc_result>),
+}
+
+impl Deref for RpcResult {
+    type Target = rpc_result;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// An error occurred during a remote procedure call. [More](https://core.telegram.org/api/errors)
+pub enum RpcError {
+    Boxed(Box<rpc_error>),
+}
+
+impl Deref for RpcError {
+    type Target = rpc_error;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// [Packed object](https://core.telegram.org/mtproto/service_messages#packed-object)
+pub enum GzipPacked {
+    Boxed(Box<gzip_packed>),
+}
+
+impl Deref for GzipPacked {
+    type Target = gzip_packed;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// [Acknowledgment of receipt](https://core.telegram.org/mtproto/service_messages_about_messages#acknowledgment-of-receipt)
+pub enum MsgsAck {
+    Boxed(Box<msgs_ack>),
+}
+
+impl Deref for MsgsAck {
+    type Target = msgs_ack;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// The response of `ping`. [More](https://core.telegram.org/mtproto/service_messages#ping-messages-pingpong)
+pub enum Pong {
+    Boxed(Box<pong>),
+}
+
+impl Deref for Pong {
+    type Target = pong;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// [Notice of ignored error message](https://core.telegram.org/mtproto/service_messages_about_messages#notice-of-ignored-error-message)
+pub enum BadMsgNotification {
+    /// The message is ignored.
+    Bad_msg_notification(Box<bad_msg_notification>),
+    /// The message is ignored because of the incorrect server salt.
+    Bad_server_salt(Box<bad_server_salt>),
+}
+
+impl BadMsgNotification {
+    /// Returns the message id of the ignored message.
+    pub fn bad_msg_id(&self) -> i64 {
+        match self {
+            Self::Bad_msg_notification(v) => v.bad_msg_id,
+            Self::Bad_server_salt(v) => v.bad_msg_id,
+        }
+    }
+
+    /// Returns the error code.
+    pub fn error_code(&self) -> BadMsgCode {
+        match self {
+            Self::Bad_msg_notification(v) => v.error_code.into(),
+            Self::Bad_server_salt(v) => v.error_code.into(),
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// The response of `get_future_salts`. [More](https://core.telegram.org/mtproto/service_messages#request-for-several-future-salts)
+pub enum FutureSalts {
+    Boxed(Box<future_salts>),
+}
+
+impl Deref for FutureSalts {
+    type Target = future_salts;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// [Informational message regarding status of messages](https://core.telegram.org/mtproto/service_messages_about_messages#informational-message-regarding-status-of-messages)
+pub enum MsgsStateInfo {
+    Boxed(Box<msgs_state_info>),
+}
+
+impl Deref for MsgsStateInfo {
+    type Target = msgs_state_info;
+    fn deref(&self) -> &Self::Target {
+        match self {
+            Self::Boxed(v) => v,
+        }
+    }
+}
+
+#[derive(
+    Clone, Debug, tdlib_rs_impl::OptDeserialize, tdlib_rs_impl::From1, tdlib_rs_impl::Serialize,
+)]
+/// [Extended voluntary communication of status of one message](https://core.telegram.org/mtproto/service_messages_about_messages#extended-voluntary-communication-of-status-of-one-message)
+pub enum MsgDetailedInfo {
+    /// The message is a response to a request.
+    Msg_detailed_info(Box<msg_detailed_info>),
+    /// The message is not a response.
+    Msg_new_detailed_info(Box<msg_new_detailed_info>),
+}
+
+impl MsgDetailedInfo {
+    /// Returns the message id of the message which is described.
+    pub fn answer_msg_id(&self) -> i64 {
+        match self {
+            Self::Msg_detailed_info(v) => v.answer_msg_id,
+            Self::Msg_new_detailed_info(v) => v.answer_msg_id,
+        }
+    }
+}
+
+#[test]
+fn test_set_client_dh_params_answer() {
+    let new_nonce = I256::from(0x1234567890u128);
+    let aux_hash = 0x0102030405060708;
+    let answer = Set_client_DH_params_answer::from(dh_gen_retry {
+        nonce: 1,
+        server_nonce: 2,
+        new_nonce_hash2: Set_client_DH_params_answer::calc_new_nonce_hash(&new_nonce, 2, aux_hash),
+    });
+    assert_eq!(answer.nonces(), (1, 2));
+    assert!(answer.check_new_nonce_hash(&new_nonce, aux_hash));
+    assert!(!answer.check_new_nonce_hash(&new_nonce, aux_hash + 1));
+    let data = answer.serialize_to_bytes();
+    assert_eq!(&data[..4], &dh_gen_retry::type_id2().to_le_bytes());
+    let answer = Set_client_DH_params_answer::deserialize_from_bytes(&data).unwrap();
+    assert!(matches!(
+        answer,
+        Set_client_DH_params_answer::Dh_gen_retry(_)
+    ));
+    assert!(answer.check_new_nonce_hash(&new_nonce, aux_hash));
+}
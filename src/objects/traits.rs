@@ -80,3 +80,16 @@ where
         Ok(T::opt_deserialize(data)?.try_err("No suitable variant found.")?)
     }
 }
+
+/// A remote procedure which can be invoked.
+pub trait Function: Serialize + TypeId {
+    /// The result type
+    type Return: Deserialize<Error = crate::objects::DeserializeError>;
+    /// Serialize the function with its constructor id.
+    fn serialize_boxed(&self) -> BytesMut {
+        let mut data = BytesMut::with_capacity(4);
+        data.extend_from_slice(&Self::type_id2().to_le_bytes());
+        data.extend_from_slice(&self.serialize_to_bytes());
+        data
+    }
+}
@@ -21,7 +21,7 @@ fn pq_add_mul(c: u64, a: u64, b: u64, pq: u64) -> u64 {
         }
         b >>= 1;
     }
-    return c;
+    c
 }
 
 fn pq_gcd(a: u64, b: u64) -> u64 {
@@ -83,8 +83,7 @@ fn pq_factorize1(pq: u64) -> u64 {
         }
         i += 1;
     }
-    if g != 0 {
-        let other = pq / g;
+    if let Some(other) = pq.checked_div(g) {
         if other < g {
             g = other;
         }
@@ -92,7 +91,10 @@ fn pq_factorize1(pq: u64) -> u64 {
     g
 }
 
-fn pq_factorize_big(pq: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>, openssl::error::ErrorStack> {
+/// The factorize result: `(p, q)`
+type PQ = (Vec<u8>, Vec<u8>);
+
+fn pq_factorize_big(pq: &[u8]) -> Result<Option<PQ>, openssl::error::ErrorStack> {
     let mut ctx = BigNumContext::new()?;
     let mut p = BigNum::new()?;
     let mut q = BigNum::new()?;
@@ -144,17 +146,17 @@ fn pq_factorize_big(pq: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>, openssl::er
     }
 }
 
-pub fn pq_factorize(pqs: &[u8]) -> Result<Option<(Vec<u8>, Vec<u8>)>, openssl::error::ErrorStack> {
+pub fn pq_factorize(pqs: &[u8]) -> Result<Option<PQ>, openssl::error::ErrorStack> {
     let size = pqs.len();
     if size > 8 || (size == 8 && (pqs[0] & 128) != 0) {
         return pq_factorize_big(pqs);
     }
     let mut pq = 0u64;
-    for i in 0..size {
-        pq = (pq << 8) | (pqs[i] as u64);
+    for i in pqs {
+        pq = (pq << 8) | (*i as u64);
     }
     let p = pq_factorize1(pq);
-    if p == 0 || pq % p != 0 {
+    if p == 0 || !pq.is_multiple_of(p) {
         Ok(None)
     } else {
         let mut rp = p.to_be_bytes().to_vec();